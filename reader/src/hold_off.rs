@@ -0,0 +1,107 @@
+use anwesenheit_store::tally_id::TallyID;
+
+/// Hold-off window used until it is changed at runtime, in milliseconds
+pub const DEFAULT_WINDOW_MS: u32 = 2_000;
+
+/// Number of cards whose last read is remembered
+const RECENT_CARDS: usize = 8;
+
+/// Suppresses the repeated frames the reader sends while a card is held.
+///
+/// A card is reported again only after it was not read for a whole window,
+/// so one tap yields one event however long the card is held.
+/// Each card has its own window, a different card is reported right away.
+#[derive(Default)]
+pub struct HoldOff {
+    /// Cards with the time they were last read, oldest first
+    recent: heapless::Vec<(TallyID, u64), RECENT_CARDS>,
+}
+
+impl HoldOff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a read of `id` at `now_ms`, returns if it should be reported
+    pub fn accept(&mut self, id: TallyID, now_ms: u64, window_ms: u32) -> bool {
+        let window_ms = window_ms as u64;
+        self.recent
+            .retain(|&(_, seen)| now_ms.saturating_sub(seen) < window_ms);
+
+        let held = match self.recent.iter().position(|&(recent, _)| recent == id) {
+            Some(i) => {
+                self.recent.remove(i);
+                true
+            }
+            None => false,
+        };
+
+        if self.recent.is_full() {
+            self.recent.remove(0);
+        }
+        // Can't fail, there is space left after the removal above
+        let _ = self.recent.push((id, now_ms));
+
+        !held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: u32 = 1_000;
+
+    fn id(n: u8) -> TallyID {
+        TallyID::from_bytes([0, 0, 0, 0, n])
+    }
+
+    #[test]
+    fn held_card_is_reported_once() {
+        let mut hold_off = HoldOff::new();
+
+        assert!(hold_off.accept(id(1), 0, WINDOW));
+        // The reader repeats the frame every few hundred ms while the card is held
+        for now in (300..5_000).step_by(300) {
+            assert!(!hold_off.accept(id(1), now, WINDOW));
+        }
+    }
+
+    #[test]
+    fn card_is_reported_again_after_the_window() {
+        let mut hold_off = HoldOff::new();
+
+        assert!(hold_off.accept(id(1), 0, WINDOW));
+        assert!(!hold_off.accept(id(1), 999, WINDOW));
+        assert!(hold_off.accept(id(1), 1_999, WINDOW));
+    }
+
+    #[test]
+    fn other_cards_are_not_held_off() {
+        let mut hold_off = HoldOff::new();
+
+        assert!(hold_off.accept(id(1), 0, WINDOW));
+        assert!(hold_off.accept(id(2), 100, WINDOW));
+        assert!(!hold_off.accept(id(1), 200, WINDOW));
+        assert!(!hold_off.accept(id(2), 300, WINDOW));
+    }
+
+    #[test]
+    fn zero_window_reports_every_read() {
+        let mut hold_off = HoldOff::new();
+
+        assert!(hold_off.accept(id(1), 0, 0));
+        assert!(hold_off.accept(id(1), 0, 0));
+    }
+
+    #[test]
+    fn oldest_card_is_forgotten_when_full() {
+        let mut hold_off = HoldOff::new();
+
+        for n in 0..=RECENT_CARDS as u8 {
+            assert!(hold_off.accept(id(n), n as u64, WINDOW));
+        }
+        assert!(hold_off.accept(id(0), 100, WINDOW));
+        assert!(!hold_off.accept(id(RECENT_CARDS as u8), 100, WINDOW));
+    }
+}
@@ -0,0 +1,6 @@
+//! Protocol logic of the RFID reader, independent of the hardware
+//! so it can be tested on the host.
+#![cfg_attr(not(test), no_std)]
+
+pub mod hold_off;
+pub mod rfid_frame;
@@ -0,0 +1,185 @@
+use anwesenheit_store::tally_id::{TallyID, decode_hex};
+
+/// Start of text, begins a frame
+const STX: u8 = 0x02;
+/// End of text, ends a frame
+const ETX: u8 = 0x03;
+/// Number of hex chars between STX and ETX
+pub const PAYLOAD_LEN: usize = 12;
+
+/// Why a frame of the RFID reader was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame was cut off, too long or contained other bytes than hex chars
+    Malformed,
+    /// The payload contains chars that are not hex
+    NotHex,
+    /// The checksum does not match the card number, e.g. after a weak read
+    Checksum,
+}
+
+/// Card number from the payload of a frame.
+/// The payload is the card number as 10 hex chars,
+/// followed by the XOR of its bytes as 2 hex chars.
+pub fn card_id(payload: &[u8; PAYLOAD_LEN]) -> Result<TallyID, FrameError> {
+    let (number, checksum) = payload.split_at(2 * TallyID::LEN);
+
+    let id = TallyID::try_from(number).map_err(|_| FrameError::NotHex)?;
+    let [checksum] = decode_hex(checksum).ok_or(FrameError::NotHex)?;
+
+    if id.to_bytes().iter().fold(0, |acc, byte| acc ^ byte) != checksum {
+        return Err(FrameError::Checksum);
+    }
+    Ok(id)
+}
+
+/// Decodes the frames of the RFID reader from its UART stream.
+///
+/// A frame is `STX`, 12 hex chars and `ETX`.
+/// Frames may be split across reads, so the decoder keeps the state between them.
+/// A frame that breaks off is reported as malformed,
+/// anything after it is skipped until the next `STX`.
+#[derive(Default)]
+pub struct FrameDecoder {
+    payload: heapless::Vec<u8, PAYLOAD_LEN>,
+    in_frame: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one byte, returns the payload if the byte completes a frame
+    /// or an error if it breaks the current one
+    pub fn push(&mut self, byte: u8) -> Option<Result<[u8; PAYLOAD_LEN], FrameError>> {
+        match byte {
+            // A new frame always starts over, this also resynchronises after garbage
+            STX => {
+                let interrupted = self.in_frame;
+                self.payload.clear();
+                self.in_frame = true;
+                interrupted.then_some(Err(FrameError::Malformed))
+            }
+            _ if !self.in_frame => None,
+            ETX => {
+                self.in_frame = false;
+                Some(
+                    self.payload
+                        .as_slice()
+                        .try_into()
+                        .map_err(|_| FrameError::Malformed),
+                )
+            }
+            _ if byte.is_ascii_hexdigit() && self.payload.push(byte).is_ok() => None,
+            // Not hex or too long for a frame
+            _ => {
+                self.in_frame = false;
+                Some(Err(FrameError::Malformed))
+            }
+        }
+    }
+
+    /// Feed the bytes of a read, yields the results of all frames they complete or break
+    pub fn decode<'a>(
+        &'a mut self,
+        bytes: &'a [u8],
+    ) -> impl Iterator<Item = Result<[u8; PAYLOAD_LEN], FrameError>> + 'a {
+        bytes.iter().filter_map(move |&byte| self.push(byte))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: &[u8] = b"\x020123456789AB\x03";
+    const PAYLOAD: &[u8; PAYLOAD_LEN] = b"0123456789AB";
+
+    fn decode_all(
+        decoder: &mut FrameDecoder,
+        bytes: &[u8],
+    ) -> Vec<Result<[u8; PAYLOAD_LEN], FrameError>> {
+        decoder.decode(bytes).collect()
+    }
+
+    #[test]
+    fn complete_frame() {
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decode_all(&mut decoder, FRAME), [Ok(*PAYLOAD)]);
+    }
+
+    #[test]
+    fn frame_split_across_reads() {
+        let mut decoder = FrameDecoder::new();
+
+        assert!(decode_all(&mut decoder, &FRAME[..5]).is_empty());
+        assert!(decode_all(&mut decoder, &FRAME[5..13]).is_empty());
+        assert_eq!(decode_all(&mut decoder, &FRAME[13..]), [Ok(*PAYLOAD)]);
+    }
+
+    #[test]
+    fn multiple_frames_in_one_read() {
+        let mut decoder = FrameDecoder::new();
+        let bytes = [FRAME, FRAME].concat();
+
+        assert_eq!(
+            decode_all(&mut decoder, &bytes),
+            [Ok(*PAYLOAD), Ok(*PAYLOAD)]
+        );
+    }
+
+    #[test]
+    fn resynchronises_after_garbage() {
+        let mut decoder = FrameDecoder::new();
+        let bytes = [b"\xFF\x03garbage\x020123".as_slice(), FRAME].concat();
+
+        // The cut off frame is reported, the garbage before it is not
+        assert_eq!(
+            decode_all(&mut decoder, &bytes),
+            [Err(FrameError::Malformed), Ok(*PAYLOAD)]
+        );
+    }
+
+    #[test]
+    fn invalid_frames_are_reported_once() {
+        let mut decoder = FrameDecoder::new();
+        let malformed = [Err(FrameError::Malformed)];
+
+        // Too short, too long and not hex
+        assert_eq!(decode_all(&mut decoder, b"\x020123456789A\x03"), malformed);
+        assert_eq!(
+            decode_all(&mut decoder, b"\x020123456789ABC\x03"),
+            malformed
+        );
+        assert_eq!(decode_all(&mut decoder, b"\x020123456789AX\x03"), malformed);
+
+        assert_eq!(decode_all(&mut decoder, FRAME), [Ok(*PAYLOAD)]);
+    }
+
+    #[test]
+    fn frame_with_valid_checksum() {
+        // 0x01 ^ 0x23 ^ 0x45 ^ 0x67 ^ 0x89 = 0x89
+        let id = card_id(b"012345678989").unwrap();
+        assert_eq!(id.to_bytes(), [0x01, 0x23, 0x45, 0x67, 0x89]);
+    }
+
+    #[test]
+    fn frame_with_bad_checksum() {
+        assert_eq!(card_id(b"012345678988"), Err(FrameError::Checksum));
+    }
+
+    #[test]
+    fn frame_with_non_hex_chars() {
+        assert_eq!(card_id(b"01234567898X"), Err(FrameError::NotHex));
+        assert_eq!(card_id(b"012345678X89"), Err(FrameError::NotHex));
+    }
+
+    #[test]
+    fn completed_frames_are_not_repeated() {
+        let mut decoder = FrameDecoder::new();
+
+        assert_eq!(decode_all(&mut decoder, FRAME), [Ok(*PAYLOAD)]);
+        assert!(decode_all(&mut decoder, b"\x03").is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    cell::Cell,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use log::error;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::store::{
+    AttendanceDay, IDMapping, ScanMode,
+    day::Day,
+    persistence::{Persistence, StorageError},
+};
+
+/// Stores the data in a directory with the layout of the SD card.
+/// A copy of a card only works once its day files are in the `YYYY/MM/` directories,
+/// neither the day files of older firmware in the root nor temporary files are read.
+pub struct DirPersistence {
+    dir: PathBuf,
+    /// Cleared to simulate a removed SD card
+    pub available: Rc<Cell<bool>>,
+}
+
+impl DirPersistence {
+    const MAPPING_FILENAME: &'static str = "MAPPING.JS";
+    const MODE_FILENAME: &'static str = "MODE.JS";
+    const FILE_EXTENSION: &'static str = Day::FILE_EXTENSION;
+    const DISCARDED_EXTENSION: &'static str = "BAD";
+
+    pub fn new(dir: PathBuf) -> Self {
+        DirPersistence {
+            dir,
+            available: Rc::new(Cell::new(true)),
+        }
+    }
+
+    /// `YYYY/MM/YYYYMMDD.JSN`
+    fn day_path(&self, day: Day) -> PathBuf {
+        let (year, month, _) = day.to_ymd();
+
+        self.dir
+            .join(format!("{year:04}"))
+            .join(format!("{month:02}"))
+            .join(format!("{}.{}", day.to_date_string(), Self::FILE_EXTENSION))
+    }
+
+    fn check_available(&self) -> Result<(), StorageError> {
+        if self.available.get() {
+            Ok(())
+        } else {
+            Err(StorageError::Device)
+        }
+    }
+
+    fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, StorageError> {
+        match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|_| StorageError::Serialization),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    /// Write to a temporary file and rename it, so the file is never incomplete
+    fn write<T: Serialize>(path: &Path, data: &T) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(data).map_err(|_| StorageError::Serialization)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        let temp_path = path.with_extension("TMP");
+        fs::write(&temp_path, json).map_err(io_error)?;
+        fs::rename(temp_path, path).map_err(io_error)
+    }
+}
+
+impl Persistence for DirPersistence {
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        self.check_available()?;
+        Self::read(&self.day_path(day))
+    }
+
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError> {
+        self.check_available()?;
+        Self::write(&self.day_path(day), data)
+    }
+
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError> {
+        self.check_available()?;
+
+        let mut days = Vec::new();
+        for year_dir in subdirs(&self.dir)? {
+            for month_dir in subdirs(&year_dir)? {
+                for entry in fs::read_dir(month_dir).map_err(io_error)? {
+                    let path = entry.map_err(io_error)?.path();
+                    if path.extension().and_then(|e| e.to_str()) != Some(Self::FILE_EXTENSION) {
+                        continue;
+                    }
+
+                    let stem = path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or_default();
+                    if let Ok(day) = Day::from_date_str(stem) {
+                        days.push(day);
+                    }
+                }
+            }
+        }
+
+        days.sort_unstable();
+        Ok(days)
+    }
+
+    /// The file is renamed to `YYYYMMDD.BAD`
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError> {
+        self.check_available()?;
+
+        let path = self.day_path(day);
+        match fs::rename(&path, path.with_extension(Self::DISCARDED_EXTENSION)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError> {
+        self.check_available()?;
+        Self::read(&self.dir.join(Self::MAPPING_FILENAME))
+    }
+
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError> {
+        self.check_available()?;
+        Self::write(&self.dir.join(Self::MAPPING_FILENAME), data)
+    }
+
+    /// The file is renamed to `MAPPING.BAD`
+    async fn discard_mapping(&mut self) -> Result<(), StorageError> {
+        self.check_available()?;
+
+        let path = self.dir.join(Self::MAPPING_FILENAME);
+        match fs::rename(&path, path.with_extension(Self::DISCARDED_EXTENSION)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError> {
+        self.check_available()?;
+        Self::read(&self.dir.join(Self::MODE_FILENAME))
+    }
+
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        self.check_available()?;
+        Self::write(&self.dir.join(Self::MODE_FILENAME), &mode)
+    }
+}
+
+fn subdirs(dir: &Path) -> Result<Vec<PathBuf>, StorageError> {
+    let mut dirs = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_dir() {
+            dirs.push(path);
+        }
+    }
+
+    Ok(dirs)
+}
+
+fn io_error(e: io::Error) -> StorageError {
+    error!("Storage error: {e}");
+
+    match e.kind() {
+        io::ErrorKind::StorageFull => StorageError::Full,
+        _ => StorageError::Io,
+    }
+}
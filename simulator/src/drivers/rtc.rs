@@ -0,0 +1,29 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::info;
+
+/// Clock of the simulator.
+/// Runs with the time of the host, setting it keeps the offset to the host.
+#[derive(Default)]
+pub struct RTCClock {
+    offset: i64,
+}
+
+impl RTCClock {
+    fn host_time() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0)
+    }
+
+    pub async fn get_time(&mut self) -> u64 {
+        (Self::host_time() + self.offset).max(0) as u64
+    }
+
+    pub async fn set_time(&mut self, timestamp: u64) -> Result<(), ()> {
+        self.offset = timestamp as i64 - Self::host_time();
+        info!("Clock set to {timestamp}");
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use embassy_time::{Duration, Timer};
+use log::info;
+
+use crate::FEEDBACK_STATE;
+
+pub use state::{FeedbackState, Step};
+
+// The patterns are shared with the firmware
+#[path = "../../src/feedback/state.rs"]
+mod state;
+
+/// Logs the LED and buzzer pattern of each feedback state
+pub async fn feedback_task() {
+    loop {
+        let feedback_state = FEEDBACK_STATE.wait().await;
+        info!("Feedback state: {:?}", feedback_state);
+
+        for step in feedback_state.steps() {
+            match *step {
+                Step::Led(color) => {
+                    info!("  LED #{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+                }
+                Step::Beep(millis) => {
+                    info!("  Beep {millis} ms");
+                    Timer::after(Duration::from_millis(millis)).await;
+                }
+                Step::Pause(millis) => Timer::after(Duration::from_millis(millis)).await,
+            }
+        }
+    }
+}
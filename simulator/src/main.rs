@@ -0,0 +1,215 @@
+//! Runs the attendance pipeline on the development machine.
+//! IDs typed into stdin are handled like scans of the NFC reader.
+#![feature(impl_trait_in_assoc_type)]
+
+use std::{
+    cell::Cell,
+    path::PathBuf,
+    rc::Rc,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use anwesenheit_reader as reader;
+use anwesenheit_store as store;
+use embassy_sync::{
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
+    mutex::Mutex,
+    pubsub::{
+        ImmediatePublisher, PubSubChannel, Publisher, Subscriber,
+        WaitResult::{Lagged, Message},
+    },
+    signal::Signal,
+};
+use embassy_time::Instant;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{
+    dir_persistence::DirPersistence,
+    drivers::rtc::RTCClock,
+    feedback::FeedbackState,
+    reader::hold_off::{DEFAULT_WINDOW_MS, HoldOff},
+    store::{IDStore, ScanResult, day::Day, tally_id::TallyID},
+    webserver::ScanEvent,
+};
+
+extern crate alloc;
+
+mod dir_persistence;
+mod drivers;
+mod feedback;
+mod webserver;
+
+static FEEDBACK_STATE: Signal<CriticalSectionRawMutex, FeedbackState> = Signal::new();
+
+type TallyChannel = PubSubChannel<NoopRawMutex, TallyID, 8, 2, 1>;
+type TallyPublisher = Publisher<'static, NoopRawMutex, TallyID, 8, 2, 1>;
+type TallySubscriber = Subscriber<'static, NoopRawMutex, TallyID, 8, 2, 1>;
+type ScanChannel = PubSubChannel<NoopRawMutex, ScanEvent, 8, 2, 1>;
+type ScanPublisher = ImmediatePublisher<'static, NoopRawMutex, ScanEvent, 8, 2, 1>;
+type ScanSubscriber = Subscriber<'static, NoopRawMutex, ScanEvent, 8, 2, 1>;
+type UsedStore = IDStore<DirPersistence>;
+
+const DEFAULT_DATA_DIR: &str = "sim-data";
+/// The web UI dev server forwards API requests to this port
+const DEFAULT_PORT: u16 = 3000;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut args = std::env::args().skip(1);
+    let data_dir = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+    let port = args
+        .next()
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+
+    if let Err(e) = std::fs::create_dir_all(&data_dir) {
+        error!("Failed to create {}: {e}", data_dir.display());
+        return;
+    }
+    info!("Storing data in {}", data_dir.display());
+
+    let persistence_layer = DirPersistence::new(data_dir);
+    let card_inserted = persistence_layer.available.clone();
+
+    let rtc = Rc::new(Mutex::new(RTCClock::default()));
+    let today: Day = rtc.lock().await.get_time().await.into();
+    let store: UsedStore = IDStore::new_from_storage(persistence_layer, today).await;
+    let shared_store = Rc::new(Mutex::new(store));
+
+    let chan: &'static TallyChannel = Box::leak(Box::new(PubSubChannel::new()));
+    let publisher: TallyPublisher = chan.publisher().unwrap();
+    let sub: TallySubscriber = chan.subscriber().unwrap();
+    let scans: &'static ScanChannel = Box::leak(Box::new(PubSubChannel::new()));
+    let scan_publisher: ScanPublisher = scans.immediate_publisher();
+    let hold_off_ms: &'static AtomicU32 = Box::leak(Box::new(AtomicU32::new(DEFAULT_WINDOW_MS)));
+    // Only settable, typed IDs are never rejected
+    let signal_rejected: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async move {
+            let webserver_store = shared_store.clone();
+            let webserver_rtc = rtc.clone();
+            tokio::task::spawn_local(async move {
+                if let Err(e) = webserver::run_webserver(
+                    port,
+                    webserver_store,
+                    webserver_rtc,
+                    scans,
+                    hold_off_ms,
+                    signal_rejected,
+                )
+                .await
+                {
+                    error!("Web server failed: {e}");
+                }
+            });
+            tokio::task::spawn_local(feedback::feedback_task());
+            tokio::task::spawn_local(scan_task(sub, scan_publisher, shared_store.clone(), rtc));
+
+            FEEDBACK_STATE.signal(FeedbackState::Startup);
+            read_input(publisher, shared_store, card_inserted, hold_off_ms).await;
+        })
+        .await;
+}
+
+/// Handles scans like the main loop of the firmware
+async fn scan_task(
+    mut sub: TallySubscriber,
+    scan_publisher: ScanPublisher,
+    store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
+    rtc: Rc<Mutex<CriticalSectionRawMutex, RTCClock>>,
+) {
+    loop {
+        match sub.next_message().await {
+            Lagged(_) => debug!("Lagged"),
+            Message(msg) => {
+                let now = rtc.lock().await.get_time().await;
+                let (result, known) = {
+                    let mut store = store.lock().await;
+                    let result = store.add_id(msg, now).await;
+                    (result, store.is_known(&msg))
+                };
+
+                match &result {
+                    Ok(ScanResult::Added) => info!("{msg} is present"),
+                    Ok(ScanResult::AlreadyPresent) => info!("{msg} was already present"),
+                    Ok(ScanResult::CheckedIn) => info!("{msg} checked in"),
+                    Ok(ScanResult::CheckedOut { present_secs }) => info!(
+                        "{msg} checked out, present for {} min today",
+                        present_secs / 60
+                    ),
+                    Err(e) => error!("Failed to persist scan of {msg}: {e:?}"),
+                }
+                if !known {
+                    info!("{msg} has no name yet");
+                }
+
+                FEEDBACK_STATE.signal(FeedbackState::for_scan(&result, known));
+
+                scan_publisher.publish_immediate(ScanEvent {
+                    id: msg,
+                    already_present: matches!(result, Ok(ScanResult::AlreadyPresent)),
+                });
+            }
+        }
+    }
+}
+
+/// Reads commands from stdin.
+/// A hex ID simulates a scan, `eject` and `insert` simulate the SD card.
+/// Scans pass the same hold-off as the frames of the reader.
+async fn read_input(
+    publisher: TallyPublisher,
+    store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
+    card_inserted: Rc<Cell<bool>>,
+    hold_off_ms: &'static AtomicU32,
+) {
+    let mut hold_off = HoldOff::new();
+
+    info!("Type an ID to scan it, `eject` or `insert` to remove or insert the SD card");
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match line.trim() {
+            "" => {}
+            "eject" => {
+                card_inserted.set(false);
+                info!("SD card removed");
+                FEEDBACK_STATE.signal(FeedbackState::Nack);
+            }
+            "insert" => {
+                card_inserted.set(true);
+                info!("SD card inserted");
+                // Write everything that was scanned while the card was missing
+                match store.lock().await.flush().await {
+                    Ok(()) => FEEDBACK_STATE.signal(FeedbackState::Ack),
+                    Err(e) => {
+                        error!("Failed to write pending data: {e:?}");
+                        FEEDBACK_STATE.signal(FeedbackState::Error);
+                    }
+                }
+            }
+            input => match input.parse::<TallyID>() {
+                Ok(id) => {
+                    let now = Instant::now().as_millis();
+                    if hold_off.accept(id, now, hold_off_ms.load(Ordering::Relaxed)) {
+                        publisher.publish(id).await;
+                    } else {
+                        info!("{id} held off");
+                    }
+                }
+                Err(()) => warn!("Not a tally ID: {input}"),
+            },
+        }
+    }
+
+    // Keep serving the web UI when stdin is closed, e.g. when running in the background
+    std::future::pending::<()>().await;
+}
@@ -0,0 +1,74 @@
+use std::{
+    net::Ipv4Addr,
+    rc::Rc,
+    sync::atomic::{AtomicBool, AtomicU32},
+    time::Duration,
+};
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use log::{info, warn};
+use picoserve::AppWithStateBuilder;
+
+use crate::{
+    ScanChannel, UsedStore,
+    drivers::rtc::RTCClock,
+    webserver::app::{AppProps, AppState},
+};
+
+// The web app is shared with the firmware, only the server differs
+#[path = "../../../src/webserver/api.rs"]
+mod api;
+#[path = "../../../src/webserver/app.rs"]
+mod app;
+#[path = "../../../src/webserver/assets.rs"]
+mod assets;
+#[path = "../../../src/webserver/csv.rs"]
+mod csv;
+#[path = "../../../src/webserver/sse.rs"]
+mod sse;
+
+pub use sse::ScanEvent;
+
+pub async fn run_webserver(
+    port: u16,
+    store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
+    rtc: Rc<Mutex<CriticalSectionRawMutex, RTCClock>>,
+    chan: &'static ScanChannel,
+    hold_off_ms: &'static AtomicU32,
+    signal_rejected: &'static AtomicBool,
+) -> std::io::Result<()> {
+    // Lives as long as the simulator, like the statics of the firmware
+    let app: &'static _ = Box::leak(Box::new(AppProps.build_app()));
+    let state: &'static _ = Box::leak(Box::new(AppState {
+        store,
+        rtc,
+        chan,
+        hold_off_ms,
+        signal_rejected,
+    }));
+    let config: &'static _ = Box::leak(Box::new(picoserve::Config::new(picoserve::Timeouts {
+        start_read_request: Some(Duration::from_secs(5)),
+        persistent_start_read_request: Some(Duration::from_secs(5)),
+        read_request: Some(Duration::from_secs(5)),
+        write: Some(Duration::from_secs(5)),
+    })));
+
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    info!("Web server listening on http://localhost:{port}");
+
+    loop {
+        let (stream, remote_address) = listener.accept().await?;
+
+        tokio::task::spawn_local(async move {
+            let mut http_buffer = [0u8; 2048];
+
+            if let Err(e) =
+                picoserve::Server::new(&app.shared().with_state(state), config, &mut http_buffer)
+                    .serve(stream)
+                    .await
+            {
+                warn!("Connection from {remote_address} failed: {e:?}");
+            }
+        });
+    }
+}
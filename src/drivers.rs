@@ -1,3 +1,4 @@
 pub mod nfc_reader;
 pub mod rtc;
 pub mod buzzer;
+pub mod fram;
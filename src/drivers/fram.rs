@@ -1,9 +1,73 @@
+use embedded_hal_async::i2c::{I2c, Operation};
+
 const DEVICE_TYPE_CODE: u8 = 0b10100000;
 
-const DEVICE_ADDRESS_CODE: u8 = 0b000000;   // 3 bits for device address | default A0 = 0 A1 = 0 A2 = 0 
+const DEVICE_ADDRESS_CODE: u8 = 0b000000; // 3 bits for device address | default A0 = 0 A1 = 0 A2 = 0
+
+/// 7 bit I2C address of the FRAM, without the R/W bit
+const DEVICE_ADDRESS: u8 = (DEVICE_TYPE_CODE | DEVICE_ADDRESS_CODE) >> 1;
+
+/// Size of the FM24V02A in bytes
+pub const FRAM_SIZE: usize = 32 * 1024;
+
+#[derive(Debug)]
+pub enum FramError<E> {
+    I2c(E),
+    /// The access would wrap around at the end of the memory
+    OutOfBounds,
+}
+
+/// Driver for the FM24 FRAM on the shared I2C bus
+///
+/// Unlike an EEPROM the FRAM has no page buffer and no write delay.
+/// Any number of bytes can be read or written sequentially, only the end of
+/// the memory must not be crossed, as the address counter wraps around to 0.
+pub struct Fram<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: I2c> Fram<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Fram { i2c }
+    }
+
+    pub async fn read(
+        &mut self,
+        address: usize,
+        buffer: &mut [u8],
+    ) -> Result<(), FramError<I2C::Error>> {
+        let address = Self::memory_address(address, buffer.len())?;
+
+        self.i2c
+            .write_read(DEVICE_ADDRESS, &address, buffer)
+            .await
+            .map_err(FramError::I2c)
+    }
+
+    pub async fn write(
+        &mut self,
+        address: usize,
+        data: &[u8],
+    ) -> Result<(), FramError<I2C::Error>> {
+        let address = Self::memory_address(address, data.len())?;
+
+        // Consecutive writes are sent without a repeated start,
+        // so the data directly follows the memory address.
+        self.i2c
+            .transaction(
+                DEVICE_ADDRESS,
+                &mut [Operation::Write(&address), Operation::Write(data)],
+            )
+            .await
+            .map_err(FramError::I2c)
+    }
 
-const WRITE_CODE: u8 = 0b00000000; // 0 for write
-const READ_CODE: u8 = 0b00000001; // 1 for read
+    /// Big endian memory address for an access of `len` bytes at `address`
+    fn memory_address(address: usize, len: usize) -> Result<[u8; 2], FramError<I2C::Error>> {
+        if address + len > FRAM_SIZE {
+            return Err(FramError::OutOfBounds);
+        }
 
-const DEVICE_ADDRESS_WRITE: u8 = DEVICE_TYPE_CODE | DEVICE_ADDRESS_CODE | WRITE_CODE;  // I2C address write for FRAM
-const DEVICE_ADDRESS_READ: u8 = DEVICE_TYPE_CODE | DEVICE_ADDRESS_CODE | READ_CODE;  // I2C address read for FRAM 
\ No newline at end of file
+        Ok((address as u16).to_be_bytes())
+    }
+}
@@ -1,68 +1,74 @@
-use embassy_time::{Duration, Timer};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use embassy_time::Instant;
 use esp_hal::{Async, uart::Uart};
-use log::{debug, info, warn};
+use log::{debug, error, warn};
+
+use crate::{
+    FEEDBACK_STATE, TallyPublisher, feedback,
+    reader::{
+        hold_off::HoldOff,
+        rfid_frame::{FrameDecoder, card_id},
+    },
+};
 
-use crate::TallyPublisher;
+/// Number of frames from the RFID reader that were rejected since boot
+pub static REJECTED_FRAMES: AtomicU32 = AtomicU32::new(0);
 
+/// If `signal_rejected` is set, rejected frames give a Nack, so a badly held card
+/// gets scanned again. Within the hold-off window after a valid read or a Nack
+/// no Nack is given, so it never replaces the feedback of a counted scan.
 #[embassy_executor::task]
-pub async fn rfid_reader_task(mut uart_device: Uart<'static, Async>, chan: TallyPublisher) {
+pub async fn rfid_reader_task(
+    mut uart_device: Uart<'static, Async>,
+    chan: TallyPublisher,
+    hold_off_ms: &'static AtomicU32,
+    signal_rejected: &'static AtomicBool,
+) {
     let mut uart_buffer = [0u8; 64];
+    let mut decoder = FrameDecoder::new();
+    let mut hold_off = HoldOff::new();
+    // Time of the last valid read or Nack in milliseconds
+    let mut last_feedback: Option<u64> = None;
 
     loop {
         debug!("Looking for NFC...");
         match uart_device.read_async(&mut uart_buffer).await {
             Ok(n) => {
-                let mut hex_str = heapless::String::<64>::new();
+                let mut hex_str = heapless::String::<192>::new();
                 for byte in &uart_buffer[..n] {
                     core::fmt::Write::write_fmt(&mut hex_str, format_args!("{:02X} ", byte)).ok();
                 }
-                info!("Read {n} bytes from UART: {hex_str}");
+                debug!("Read {n} bytes from UART: {hex_str}");
 
-                match extract_id(&uart_buffer) {
-                    Some(read) => {
-                        chan.publish(read.try_into().unwrap()).await;
-                    }
-                    None => {
-                        warn!("Invalid read from the RFID reader");
+                for frame in decoder.decode(&uart_buffer[..n]) {
+                    let now = Instant::now().as_millis();
+                    let window = hold_off_ms.load(Ordering::Relaxed);
+
+                    match frame.and_then(|payload| card_id(&payload)) {
+                        Ok(id) => {
+                            last_feedback = Some(now);
+                            if hold_off.accept(id, now, window) {
+                                chan.publish(id).await;
+                            }
+                        }
+                        Err(e) => {
+                            let rejected = REJECTED_FRAMES.fetch_add(1, Ordering::Relaxed) + 1;
+                            warn!("Rejected frame from the RFID reader: {e:?} ({rejected} total)");
+
+                            let quiet = last_feedback
+                                .is_some_and(|last| now.saturating_sub(last) < window as u64);
+                            if signal_rejected.load(Ordering::Relaxed) && !quiet {
+                                last_feedback = Some(now);
+                                FEEDBACK_STATE.signal(feedback::FeedbackState::Nack);
+                            }
+                        }
                     }
-                };
+                }
             }
             Err(e) => {
-                log::error!("Error reading from UART: {e}");
-            }
-        }
-        Timer::after(Duration::from_millis(200)).await;
-    }
-}
-
-/// Scans the UART output and retuns the first propper read ID
-/// This ensures that only valid ID are parsed
-///
-/// A valid read looks like this:
-/// The first byte is always 0x02 (Start of text)
-/// Followed by 12 Bytes of chars
-/// Ended by 0x03 (End of text)
-pub fn extract_id(buffer: &[u8]) -> Option<[u8; 12]> {
-    const STX: u8 = 0x02; // Start of Text ASCII char
-    const ETX: u8 = 0x03; // End of Text ASCII char
-    const ID_LENGTH: usize = 12;
-    const MINIMUM_SEQUENCE: usize = ID_LENGTH + 2; // STX + 12 bytes + ETX
-
-    if buffer.len() < MINIMUM_SEQUENCE {
-        return None;
-    }
-
-    for window_start in 0..=buffer.len() - MINIMUM_SEQUENCE {
-        if buffer[window_start] == STX {
-            let id_end = window_start + ID_LENGTH + 1;
-
-            if buffer[id_end] == ETX {
-                let mut id = [0u8; ID_LENGTH];
-                id.copy_from_slice(&buffer[window_start + 1..id_end]);
-                return Some(id);
+                error!("Error reading from UART: {e}");
             }
         }
     }
-
-    None
 }
@@ -1,30 +1,44 @@
+use core::cell::Cell;
+
 use chrono::{TimeZone, Utc};
+use critical_section::Mutex;
 use ds3231::{
     Config, DS3231, DS3231Error, InterruptControl, Oscillator, SquareWaveFrequency,
     TimeRepresentation,
 };
-use esp_hal::{
-    Async,
-    i2c::{self, master::I2c},
-};
-use log::{debug, error, info};
+use embassy_time::Instant;
+use embedded_hal_async::i2c::ErrorType;
+use log::{debug, error, info, warn};
 
-use crate::{FEEDBACK_STATE, drivers, feedback};
+use crate::{FEEDBACK_STATE, drivers, feedback, init::hardware::SharedI2c};
 
 include!(concat!(env!("OUT_DIR"), "/build_time.rs"));
 
 const RTC_ADDRESS: u8 = 0x68;
 
-const SECS_PER_DAY: u64 = 86_400;
-const UNIX_OFFSET_DAYS: u64 = 719_163; // Days from 0000-03-01 to 1970-01-01
-const UTC_PLUS_ONE: u64 = 3600;
+/// Last time read from or written to the RTC and when that happened.
+/// Lets code that can't access the I2C bus, like the SD card time source, know the time.
+static LAST_TIME: Mutex<Cell<Option<(u64, Instant)>>> = Mutex::new(Cell::new(None));
+
+fn remember_time(timestamp: u64) {
+    critical_section::with(|cs| LAST_TIME.borrow(cs).set(Some((timestamp, Instant::now()))));
+}
+
+/// Current UTC time extrapolated from the last RTC access.
+/// Falls back to the build time if the RTC wasn't accessed yet.
+pub fn cached_time() -> u64 {
+    match critical_section::with(|cs| LAST_TIME.borrow(cs).get()) {
+        Some((timestamp, at)) => timestamp + at.elapsed().as_secs(),
+        None => BUILD_UNIX_TIME,
+    }
+}
 
 pub struct RTCClock {
-    dev: DS3231<I2c<'static, Async>>,
+    dev: DS3231<SharedI2c>,
 }
 
 impl RTCClock {
-    pub async fn new(i2c: i2c::master::I2c<'static, Async>) -> Self {
+    pub async fn new(i2c: SharedI2c) -> Self {
         debug!("configuring rtc...");
         let rtc = drivers::rtc::rtc_config(i2c).await;
         debug!("rtc up");
@@ -36,6 +50,7 @@ impl RTCClock {
         match self.dev.datetime().await {
             Ok(datetime) => {
                 let utc_time = datetime.and_utc().timestamp() as u64;
+                remember_time(utc_time);
                 utc_time
             }
             Err(e) => {
@@ -45,49 +60,37 @@ impl RTCClock {
             }
         }
     }
-}
-
-fn unix_to_ymd_string(timestamp: u64) -> (u16, u8, u8) {
-    // Apply UTC+1 offset
-    let ts = timestamp + UTC_PLUS_ONE;
 
-    // Convert to total days since UNIX epoch
-    let days_since_epoch = ts / SECS_PER_DAY;
-
-    // Convert to proleptic Gregorian date
-    civil_from_days(days_since_epoch as i64 + UNIX_OFFSET_DAYS as i64)
-}
-
-// This function returns (year, month, day).
-// Based on the algorithm by Howard Hinnant.
-fn civil_from_days(z: i64) -> (u16, u8, u8) {
-    let mut z = z;
-    z -= 60; // shift epoch for algorithm
-    let era = (z >= 0).then_some(z).unwrap_or(z - 146096) / 146097;
-    let doe = z - era * 146097; // [0, 146096]
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365; // [0, 399]
-    let y = yoe + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // [0, 365]
-    let mp = (5 * doy + 2) / 153; // [0, 11]
-    let d = doy - (153 * mp + 2) / 5 + 1; // [1, 31]
-    let m = mp + (if mp < 10 { 3 } else { -9 }); // [1, 12]
-    ((y + (m <= 2) as i64) as u16, m as u8, d as u8)
+    pub async fn set_time(&mut self, timestamp: u64) -> Result<(), ()> {
+        let naive_dt = Utc
+            .timestamp_opt(timestamp as i64, 0)
+            .single()
+            .ok_or(())?
+            .naive_utc();
+
+        match self.dev.set_datetime(&naive_dt).await {
+            Ok(_) => {
+                info!("RTC datetime set to: {}", naive_dt);
+                remember_time(timestamp);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to set RTC datetime: {:?}", e);
+                Err(())
+            }
+        }
+    }
 }
 
-pub async fn rtc_config(i2c: I2c<'static, Async>) -> DS3231<I2c<'static, Async>> {
-    let mut rtc: DS3231<I2c<'static, Async>> = DS3231::new(i2c, RTC_ADDRESS);
-    let naive_dt = Utc
-        .timestamp_opt(BUILD_UNIX_TIME as i64, 0)
-        .single()
-        .unwrap()
-        .naive_utc();
+pub async fn rtc_config(i2c: SharedI2c) -> DS3231<SharedI2c> {
+    let mut rtc: DS3231<SharedI2c> = DS3231::new(i2c, RTC_ADDRESS);
 
     let rtc_config = Config {
         time_representation: TimeRepresentation::TwentyFourHour,
         square_wave_frequency: SquareWaveFrequency::Hz1,
         interrupt_control: InterruptControl::Interrupt, // Enable interrupt mode
         battery_backed_square_wave: false,
-        oscillator_enable: Oscillator::Disabled,
+        oscillator_enable: Oscillator::Enabled, // Keep the time running on battery
     };
 
     match rtc.configure(&rtc_config).await {
@@ -99,19 +102,20 @@ pub async fn rtc_config(i2c: I2c<'static, Async>) -> DS3231<I2c<'static, Async>>
         }
     }
 
-    rtc.set_datetime(&naive_dt).await.unwrap_or_else(|e| {
-        FEEDBACK_STATE.signal(feedback::FeedbackState::Error);
-        error!("Failed to set RTC datetime: {:?}", e);
-    });
-    info!("RTC datetime set to: {}", naive_dt);
-
     match rtc.status().await {
         Ok(mut status) => {
+            // The oscillator stop flag is set when the RTC lost power or was never set.
+            // Only then the stored time is invalid and we fall back to the build time.
+            if status.oscillator_stop_flag() {
+                warn!("RTC oscillator was stopped, time is invalid");
+                set_build_time(&mut rtc).await;
+                status.set_oscillator_stop_flag(false);
+            }
             status.set_alarm1_flag(false);
             status.set_alarm2_flag(false);
             match rtc.set_status(status).await {
-                Ok(_) => info!("Alarm flags cleared"),
-                Err(e) => info!("Failed to clear alarm flags: {:?}", e),
+                Ok(_) => info!("Status flags cleared"),
+                Err(e) => info!("Failed to clear status flags: {:?}", e),
             }
         }
         Err(e) => info!("Failed to read status: {:?}", e),
@@ -120,9 +124,25 @@ pub async fn rtc_config(i2c: I2c<'static, Async>) -> DS3231<I2c<'static, Async>>
     rtc
 }
 
+async fn set_build_time(rtc: &mut DS3231<SharedI2c>) {
+    let naive_dt = Utc
+        .timestamp_opt(BUILD_UNIX_TIME as i64, 0)
+        .single()
+        .unwrap()
+        .naive_utc();
+
+    match rtc.set_datetime(&naive_dt).await {
+        Ok(_) => info!("RTC datetime set to build time: {}", naive_dt),
+        Err(e) => {
+            FEEDBACK_STATE.signal(feedback::FeedbackState::Error);
+            error!("Failed to set RTC datetime: {:?}", e);
+        }
+    }
+}
+
 pub async fn read_rtc_time<'a>(
-    rtc: &'a mut DS3231<I2c<'static, Async>>,
-) -> Result<u64, DS3231Error<esp_hal::i2c::master::Error>> {
+    rtc: &'a mut DS3231<SharedI2c>,
+) -> Result<u64, DS3231Error<<SharedI2c as ErrorType>::Error>> {
     let timestamp_result = rtc.datetime().await?;
     Ok(timestamp_result.and_utc().timestamp() as u64)
 }
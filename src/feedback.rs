@@ -3,20 +3,13 @@ use esp_hal::{peripherals, rmt::ConstChannelAccess};
 use esp_hal_smartled::SmartLedsAdapterAsync;
 use log::debug;
 use smart_leds::SmartLedsWriteAsync;
-use smart_leds::colors::{BLACK, GREEN, RED, YELLOW};
-use smart_leds::{brightness, colors::BLUE};
+use smart_leds::brightness;
 
 use crate::{FEEDBACK_STATE, init};
 
-#[derive(Copy, Clone, Debug)]
-pub enum FeedbackState {
-    Ack,
-    Nack,
-    Error,
-    Startup,
-    WIFI,
-    Idle,
-}
+pub use state::{FeedbackState, Step};
+
+mod state;
 
 const LED_LEVEL: u8 = 255;
 
@@ -34,97 +27,24 @@ pub async fn feedback_task(
     let mut buzzer = init::hardware::setup_buzzer(buzzer);
     loop {
         let feedback_state = FEEDBACK_STATE.wait().await;
-        match feedback_state {
-            FeedbackState::Ack => {
-                led.write(brightness(
-                    [GREEN; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(100)).await;
-                buzzer.set_low();
-                Timer::after(Duration::from_millis(50)).await;
-            }
-            FeedbackState::Nack => {
-                led.write(brightness(
-                    [YELLOW; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(100)).await;
-                buzzer.set_low();
-                Timer::after(Duration::from_millis(100)).await;
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(100)).await;
-                buzzer.set_low();
-                led.write(brightness(
-                    [BLACK; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-            }
-            FeedbackState::Error => {
-                led.write(brightness(
-                    [RED; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(500)).await;
-                buzzer.set_low();
-                Timer::after(Duration::from_millis(500)).await;
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(500)).await;
-                buzzer.set_low();
-            }
-            FeedbackState::Startup => {
-                led.write(brightness(
-                    [GREEN; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(10)).await;
-                buzzer.set_low();
-                Timer::after(Duration::from_millis(10)).await;
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(10)).await;
-                buzzer.set_low();
-                Timer::after(Duration::from_millis(50)).await;
-                buzzer.set_high();
-                Timer::after(Duration::from_millis(100)).await;
-                buzzer.set_low();
-                led.write(brightness(
-                    [BLACK; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-            }
-            FeedbackState::WIFI => {
-                led.write(brightness(
-                    [BLUE; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
-            }
-            FeedbackState::Idle => {
-                led.write(brightness(
-                    [GREEN; init::hardware::NUM_LEDS].into_iter(),
-                    LED_LEVEL,
-                ))
-                .await
-                .unwrap();
+        for step in feedback_state.steps() {
+            match *step {
+                Step::Led(color) => {
+                    led.write(brightness(
+                        [color; init::hardware::NUM_LEDS].into_iter(),
+                        LED_LEVEL,
+                    ))
+                    .await
+                    .unwrap();
+                }
+                Step::Beep(millis) => {
+                    buzzer.set_high();
+                    Timer::after(Duration::from_millis(millis)).await;
+                    buzzer.set_low();
+                }
+                Step::Pause(millis) => Timer::after(Duration::from_millis(millis)).await,
             }
-        };
+        }
         debug!("Feedback state: {:?}", feedback_state);
     }
 }
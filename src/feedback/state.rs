@@ -0,0 +1,110 @@
+use smart_leds::RGB8;
+use smart_leds::colors::{BLACK, BLUE, CYAN, GREEN, ORANGE, PURPLE, RED, YELLOW};
+
+use crate::store::{ScanResult, persistence::StorageError};
+
+#[derive(Copy, Clone, Debug)]
+pub enum FeedbackState {
+    Ack,
+    /// The ID was already counted today
+    AlreadyPresent,
+    /// The ID has no name yet, the cue is played after the outcome of the scan
+    UnknownCard(&'static FeedbackState),
+    Nack,
+    CheckIn,
+    CheckOut,
+    Error,
+    Startup,
+    WIFI,
+    Idle,
+}
+
+/// One step of the LED and buzzer pattern of a state
+#[derive(Copy, Clone, Debug)]
+pub enum Step {
+    /// Set all LEDs to the color
+    Led(RGB8),
+    /// Turn the buzzer on for the milliseconds
+    Beep(u64),
+    /// Wait for the milliseconds
+    Pause(u64),
+}
+
+impl FeedbackState {
+    pub fn pattern(self) -> &'static [Step] {
+        use Step::{Beep, Led, Pause};
+
+        match self {
+            FeedbackState::Ack => &[Led(GREEN), Beep(100), Pause(50)],
+            FeedbackState::AlreadyPresent => &[
+                Led(CYAN),
+                Beep(30),
+                Pause(70),
+                Beep(30),
+                Pause(70),
+                Beep(30),
+                Pause(500),
+                Led(BLACK),
+            ],
+            FeedbackState::UnknownCard(_) => &[
+                Pause(200),
+                Led(ORANGE),
+                Beep(50),
+                Pause(50),
+                Beep(50),
+                Pause(50),
+                Beep(300),
+                Pause(500),
+                Led(BLACK),
+            ],
+            FeedbackState::CheckIn => &[Led(GREEN), Beep(50), Pause(50), Beep(150)],
+            FeedbackState::CheckOut => &[
+                Led(PURPLE),
+                Beep(150),
+                Pause(50),
+                Beep(50),
+                Pause(500),
+                Led(BLACK),
+            ],
+            FeedbackState::Nack => &[Led(YELLOW), Beep(100), Pause(100), Beep(100), Led(BLACK)],
+            FeedbackState::Error => &[Led(RED), Beep(500), Pause(500), Beep(500)],
+            FeedbackState::Startup => &[
+                Led(GREEN),
+                Beep(10),
+                Pause(10),
+                Beep(10),
+                Pause(50),
+                Beep(100),
+                Led(BLACK),
+            ],
+            FeedbackState::WIFI => &[Led(BLUE)],
+            FeedbackState::Idle => &[Led(GREEN)],
+        }
+    }
+
+    /// All steps to play, including the outcome wrapped by [`FeedbackState::UnknownCard`]
+    pub fn steps(self) -> impl Iterator<Item = &'static Step> {
+        let outcome = match self {
+            FeedbackState::UnknownCard(outcome) => outcome.pattern(),
+            _ => &[],
+        };
+        outcome.iter().chain(self.pattern())
+    }
+
+    /// Feedback for the outcome of a scan, `known` is whether the ID has a name
+    pub fn for_scan(result: &Result<ScanResult, StorageError>, known: bool) -> Self {
+        let outcome = match result {
+            Err(_) => &FeedbackState::Error,
+            Ok(ScanResult::Added) => &FeedbackState::Ack,
+            Ok(ScanResult::CheckedIn) => &FeedbackState::CheckIn,
+            Ok(ScanResult::CheckedOut { .. }) => &FeedbackState::CheckOut,
+            Ok(ScanResult::AlreadyPresent) => &FeedbackState::AlreadyPresent,
+        };
+
+        if known {
+            *outcome
+        } else {
+            FeedbackState::UnknownCard(outcome)
+        }
+    }
+}
@@ -2,3 +2,5 @@ pub mod hardware;
 pub mod network;
 pub mod wifi;
 pub mod sd_card;
+pub mod fram;
+pub mod storage;
@@ -0,0 +1,484 @@
+use alloc::{vec, vec::Vec};
+use log::{error, info, warn};
+
+use crate::{
+    drivers::fram::{Fram, FramError},
+    init::hardware::SharedI2c,
+    store::{
+        Attendance, AttendanceDay, IDMapping, ScanMode,
+        day::Day,
+        persistence::{JournalEntry, Persistence, StorageError},
+        tally_id::TallyID,
+    },
+};
+
+const MAGIC: [u8; 4] = *b"FWA1";
+const HEADER_SIZE: usize = MAGIC.len();
+const MAPPING_SIZE: usize = 8 * 1024;
+/// Changes whenever the placement or the encoding of the days changes
+const LAYOUT_VERSION: u8 = 1;
+const LAYOUT_OFFSET: usize = HEADER_SIZE + MAPPING_SIZE;
+const LAYOUT_SIZE: usize = 5; // version + day slot count + day slot size
+const MODE_OFFSET: usize = LAYOUT_OFFSET + LAYOUT_SIZE;
+const NO_MODE: u8 = u8::MAX;
+const DAYS_OFFSET: usize = MODE_OFFSET + 1;
+const DAY_SIZE: usize = 4;
+const LENGTH_SIZE: usize = 2;
+/// Number of people that fit into one day.
+/// With this the FRAM next to the journal holds 13 days.
+const MAX_DAY_ATTENDANCES: usize = 100;
+const DAY_SLOT_SIZE: usize = DAY_SIZE + LENGTH_SIZE + MAX_DAY_ATTENDANCES * Attendance::ENCODED_LEN;
+const EMPTY_SLOT: u32 = u32::MAX;
+
+/// Size of the FRAM region used for the journal
+pub const JOURNAL_SIZE: usize = 1024;
+// Changes whenever the entry layout changes, so old journals get formatted
+const JOURNAL_MAGIC: [u8; 4] = *b"FWJ1";
+const JOURNAL_HEADER_SIZE: usize = JOURNAL_MAGIC.len() + 2;
+const JOURNAL_ENTRY_SIZE: usize = TallyID::LEN + 8 + 1; // id + timestamp + mode
+
+/// Persistence in a region of the FRAM
+///
+/// Layout of the region:
+/// `| magic | mapping | layout | mode | day slot 0 | day slot 1 | ... |`
+///
+/// The mapping is stored as JSON, the days in the compact encoding of
+/// [`AttendanceDay::to_bytes`], both prefixed by their length. The mode is a single byte.
+/// Each day slot additionally starts with the day it contains.
+/// Once all slots are used the oldest day gets overwritten.
+///
+/// The layout describes the day slots. If it does not match, e.g. because
+/// the region got smaller, the days and the mode are formatted and the mapping is kept.
+pub struct FramPersistence {
+    fram: Fram<SharedI2c>,
+    start: usize,
+    day_slots: usize,
+}
+
+impl FramPersistence {
+    /// Use `len` bytes of the FRAM beginning at `start`.
+    /// The region gets formatted if it does not contain our data yet.
+    pub async fn new(
+        fram: Fram<SharedI2c>,
+        start: usize,
+        len: usize,
+    ) -> Result<Self, StorageError> {
+        let day_slots = len.saturating_sub(DAYS_OFFSET) / DAY_SLOT_SIZE;
+        let mut persistence = FramPersistence {
+            fram,
+            start,
+            day_slots,
+        };
+
+        let mut magic = [0u8; HEADER_SIZE];
+        persistence.read(0, &mut magic).await?;
+        let mut layout = [0u8; LAYOUT_SIZE];
+        persistence.read(LAYOUT_OFFSET, &mut layout).await?;
+
+        if magic != MAGIC {
+            persistence.format().await?;
+        } else if layout != persistence.layout() {
+            warn!("FRAM day layout changed, the stored days are discarded");
+            persistence.format_days().await?;
+        }
+
+        Ok(persistence)
+    }
+
+    fn layout(&self) -> [u8; LAYOUT_SIZE] {
+        let slots = (self.day_slots as u16).to_le_bytes();
+        let slot_size = (DAY_SLOT_SIZE as u16).to_le_bytes();
+        [
+            LAYOUT_VERSION,
+            slots[0],
+            slots[1],
+            slot_size[0],
+            slot_size[1],
+        ]
+    }
+
+    /// Empty the mapping, the mode and all day slots.
+    pub async fn format(&mut self) -> Result<(), StorageError> {
+        self.write(HEADER_SIZE, &0u16.to_le_bytes()).await?;
+        self.format_days().await
+    }
+
+    /// Empty all day slots and the mode.
+    /// The magic is written last, so an interrupted format is repeated.
+    async fn format_days(&mut self) -> Result<(), StorageError> {
+        info!("Formatting FRAM storage with {} day slots", self.day_slots);
+
+        self.write(MODE_OFFSET, &[NO_MODE]).await?;
+        for slot in 0..self.day_slots {
+            self.write(Self::slot_offset(slot), &EMPTY_SLOT.to_le_bytes())
+                .await?;
+        }
+
+        self.write(LAYOUT_OFFSET, &self.layout()).await?;
+        self.write(0, &MAGIC).await
+    }
+
+    fn slot_offset(slot: usize) -> usize {
+        DAYS_OFFSET + slot * DAY_SLOT_SIZE
+    }
+
+    async fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), StorageError> {
+        self.fram
+            .read(self.start + offset, buffer)
+            .await
+            .map_err(fram_error)
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StorageError> {
+        self.fram
+            .write(self.start + offset, data)
+            .await
+            .map_err(fram_error)
+    }
+
+    /// Read data prefixed by its length
+    async fn read_blob(&mut self, offset: usize, size: usize) -> Result<Vec<u8>, StorageError> {
+        let mut length = [0u8; LENGTH_SIZE];
+        self.read(offset, &mut length).await?;
+
+        let length = u16::from_le_bytes(length) as usize;
+        if length > size - LENGTH_SIZE {
+            return Err(StorageError::Filesystem);
+        }
+
+        let mut data = vec![0u8; length];
+        self.read(offset + LENGTH_SIZE, &mut data).await?;
+        Ok(data)
+    }
+
+    /// Write data prefixed by its length
+    async fn write_blob(
+        &mut self,
+        offset: usize,
+        size: usize,
+        data: &[u8],
+    ) -> Result<(), StorageError> {
+        if data.len() > size - LENGTH_SIZE {
+            return Err(StorageError::Full);
+        }
+
+        self.write(offset + LENGTH_SIZE, data).await?;
+        self.write(offset, &(data.len() as u16).to_le_bytes()).await
+    }
+
+    async fn slot_day(&mut self, slot: usize) -> Result<u32, StorageError> {
+        let mut day = [0u8; DAY_SIZE];
+        self.read(Self::slot_offset(slot), &mut day).await?;
+        Ok(u32::from_le_bytes(day))
+    }
+
+    async fn find_slot(&mut self, day: Day) -> Result<Option<usize>, StorageError> {
+        for slot in 0..self.day_slots {
+            if self.slot_day(slot).await? == day.daystamp() {
+                return Ok(Some(slot));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Slot to store a new day in. This is a free slot or the one with the oldest day.
+    /// Overwriting a day is logged, its attendance is lost.
+    async fn free_slot(&mut self) -> Result<usize, StorageError> {
+        let mut oldest: Option<(usize, u32)> = None;
+
+        for slot in 0..self.day_slots {
+            let day = self.slot_day(slot).await?;
+            if day == EMPTY_SLOT {
+                return Ok(slot);
+            }
+            if oldest.is_none_or(|(_, oldest_day)| day < oldest_day) {
+                oldest = Some((slot, day));
+            }
+        }
+
+        let (slot, day) = oldest.ok_or(StorageError::Full)?;
+        error!(
+            "FRAM is full, overwriting day {} in slot {}",
+            Day::new(day).to_date_string(),
+            slot
+        );
+        Ok(slot)
+    }
+}
+
+impl Persistence for FramPersistence {
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        let Some(slot) = self.find_slot(day).await? else {
+            return Ok(None);
+        };
+
+        let data = self
+            .read_blob(Self::slot_offset(slot) + DAY_SIZE, DAY_SLOT_SIZE - DAY_SIZE)
+            .await?;
+        let day = AttendanceDay::from_bytes(day, &data).ok_or(StorageError::Serialization)?;
+
+        Ok(Some(day))
+    }
+
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError> {
+        let encoded = data.to_bytes();
+        // Check before a day gets overwritten for nothing
+        if encoded.len() > DAY_SLOT_SIZE - DAY_SIZE - LENGTH_SIZE {
+            error!(
+                "Day {} has more than {} people, it does not fit into the FRAM",
+                day.to_date_string(),
+                MAX_DAY_ATTENDANCES
+            );
+            return Err(StorageError::Full);
+        }
+
+        let slot = match self.find_slot(day).await? {
+            Some(slot) => slot,
+            None => {
+                let slot = self.free_slot().await?;
+                // Mark the slot as empty while it is rewritten
+                self.write(Self::slot_offset(slot), &EMPTY_SLOT.to_le_bytes())
+                    .await?;
+                slot
+            }
+        };
+
+        let offset = Self::slot_offset(slot);
+        self.write_blob(offset + DAY_SIZE, DAY_SLOT_SIZE - DAY_SIZE, &encoded)
+            .await?;
+        self.write(offset, &day.daystamp().to_le_bytes()).await
+    }
+
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError> {
+        let mut days = Vec::new();
+
+        for slot in 0..self.day_slots {
+            let day = self.slot_day(slot).await?;
+            if day != EMPTY_SLOT {
+                days.push(Day::new(day));
+            }
+        }
+
+        Ok(days)
+    }
+
+    /// The slot is freed, its data stays until the slot is reused
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError> {
+        let Some(slot) = self.find_slot(day).await? else {
+            return Ok(());
+        };
+
+        self.write(Self::slot_offset(slot), &EMPTY_SLOT.to_le_bytes())
+            .await
+    }
+
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError> {
+        let data = self.read_blob(HEADER_SIZE, MAPPING_SIZE).await?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let mapping: IDMapping =
+            serde_json::from_slice(&data).map_err(|_| StorageError::Serialization)?;
+
+        Ok(Some(mapping))
+    }
+
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(data).map_err(|_| StorageError::Serialization)?;
+        self.write_blob(HEADER_SIZE, MAPPING_SIZE, &json).await
+    }
+
+    /// The mapping is emptied, its data stays until a mapping is saved
+    async fn discard_mapping(&mut self) -> Result<(), StorageError> {
+        self.write(HEADER_SIZE, &0u16.to_le_bytes()).await
+    }
+
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError> {
+        let mut mode = [0u8];
+        self.read(MODE_OFFSET, &mut mode).await?;
+
+        match mode[0] {
+            0 => Ok(Some(ScanMode::Attendance)),
+            1 => Ok(Some(ScanMode::CheckInOut)),
+            NO_MODE => Ok(None),
+            _ => Err(StorageError::Serialization),
+        }
+    }
+
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        let mode = match mode {
+            ScanMode::Attendance => 0,
+            ScanMode::CheckInOut => 1,
+        };
+        self.write(MODE_OFFSET, &[mode]).await
+    }
+}
+
+/// Write-ahead journal of scans in a region of the FRAM
+///
+/// Layout of the region:
+/// `| magic | entry count | entry 0 | entry 1 | ... |`
+///
+/// The count is updated after an entry was written,
+/// so an interrupted append does not leave a broken entry behind.
+pub struct FramJournal {
+    fram: Fram<SharedI2c>,
+    start: usize,
+    capacity: usize,
+    count: usize,
+}
+
+impl FramJournal {
+    /// Use `len` bytes of the FRAM beginning at `start`
+    pub async fn new(
+        fram: Fram<SharedI2c>,
+        start: usize,
+        len: usize,
+    ) -> Result<Self, StorageError> {
+        let capacity = len.saturating_sub(JOURNAL_HEADER_SIZE) / JOURNAL_ENTRY_SIZE;
+        let mut journal = FramJournal {
+            fram,
+            start,
+            capacity,
+            count: 0,
+        };
+
+        let mut header = [0u8; JOURNAL_HEADER_SIZE];
+        journal
+            .fram
+            .read(start, &mut header)
+            .await
+            .map_err(fram_error)?;
+
+        if header[..JOURNAL_MAGIC.len()] == JOURNAL_MAGIC {
+            let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+            journal.count = count.min(capacity);
+        } else {
+            info!("Formatting FRAM journal");
+            journal.write_count(0).await?;
+            journal
+                .fram
+                .write(start, &JOURNAL_MAGIC)
+                .await
+                .map_err(fram_error)?;
+        }
+
+        Ok(journal)
+    }
+
+    async fn write_count(&mut self, count: usize) -> Result<(), StorageError> {
+        self.fram
+            .write(
+                self.start + JOURNAL_MAGIC.len(),
+                &(count as u16).to_le_bytes(),
+            )
+            .await
+            .map_err(fram_error)?;
+        self.count = count;
+        Ok(())
+    }
+
+    fn entry_offset(&self, index: usize) -> usize {
+        self.start + JOURNAL_HEADER_SIZE + index * JOURNAL_ENTRY_SIZE
+    }
+
+    async fn write_entry(
+        &mut self,
+        index: usize,
+        entry: &JournalEntry,
+    ) -> Result<(), StorageError> {
+        let mut data = [0u8; JOURNAL_ENTRY_SIZE];
+        data[..TallyID::LEN].copy_from_slice(&entry.id.to_bytes());
+        data[TallyID::LEN..TallyID::LEN + 8].copy_from_slice(&entry.timestamp.to_le_bytes());
+        data[TallyID::LEN + 8] = match entry.mode {
+            ScanMode::Attendance => 0,
+            ScanMode::CheckInOut => 1,
+        };
+
+        self.fram
+            .write(self.entry_offset(index), &data)
+            .await
+            .map_err(fram_error)
+    }
+
+    pub async fn append(&mut self, entry: JournalEntry) -> Result<(), StorageError> {
+        if self.count >= self.capacity {
+            return Err(StorageError::Full);
+        }
+
+        self.write_entry(self.count, &entry).await?;
+        self.write_count(self.count + 1).await
+    }
+
+    /// Drop the entries `keep` returns false for.
+    /// The kept entries are moved to the front before the count is updated,
+    /// so an interrupted call can only leave duplicates, which the replay skips.
+    pub async fn retain(
+        &mut self,
+        keep: impl Fn(&JournalEntry) -> bool,
+    ) -> Result<(), StorageError> {
+        let entries = self.entries().await?;
+
+        let mut kept = 0;
+        for (index, entry) in entries.iter().enumerate() {
+            if !keep(entry) {
+                continue;
+            }
+            if index != kept {
+                self.write_entry(kept, entry).await?;
+            }
+            kept += 1;
+        }
+
+        if kept == self.count {
+            return Ok(());
+        }
+        self.write_count(kept).await
+    }
+
+    pub async fn entries(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+        let mut entries = Vec::with_capacity(self.count);
+
+        for index in 0..self.count {
+            let mut data = [0u8; JOURNAL_ENTRY_SIZE];
+            self.fram
+                .read(self.entry_offset(index), &mut data)
+                .await
+                .map_err(fram_error)?;
+
+            let mut id = [0u8; TallyID::LEN];
+            id.copy_from_slice(&data[..TallyID::LEN]);
+            let mut timestamp = [0u8; 8];
+            timestamp.copy_from_slice(&data[TallyID::LEN..TallyID::LEN + 8]);
+            let mode = match data[TallyID::LEN + 8] {
+                1 => ScanMode::CheckInOut,
+                _ => ScanMode::Attendance,
+            };
+
+            entries.push(JournalEntry {
+                id: TallyID::from_bytes(id),
+                timestamp: u64::from_le_bytes(timestamp),
+                mode,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn clear(&mut self) -> Result<(), StorageError> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        self.write_count(0).await
+    }
+}
+
+fn fram_error<E: core::fmt::Debug>(e: FramError<E>) -> StorageError {
+    error!("FRAM error: {:?}", e);
+
+    match e {
+        FramError::I2c(_) => StorageError::Device,
+        FramError::OutOfBounds => StorageError::Full,
+    }
+}
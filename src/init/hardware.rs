@@ -1,7 +1,9 @@
 use core::cell::RefCell;
 use critical_section::Mutex;
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_executor::Spawner;
 use embassy_net::Stack;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::{Duration, Timer};
 use esp_hal::Blocking;
 use esp_hal::gpio::Input;
@@ -25,9 +27,14 @@ use esp_hal::{
 use esp_hal_smartled::{SmartLedsAdapterAsync, buffer_size_async};
 use esp_println::logger::init_logger;
 use log::{debug, error};
+use static_cell::make_static;
 
+use crate::drivers::fram::{FRAM_SIZE, Fram};
+use crate::drivers::rtc::RTCClock;
+use crate::init::fram::{FramJournal, FramPersistence, JOURNAL_SIZE};
 use crate::init::network;
-use crate::init::sd_card::{SDCardPersistence, setup_sdcard};
+use crate::init::sd_card::setup_sdcard;
+use crate::init::storage::{Storage, setup_storage};
 use crate::init::wifi;
 
 /*************************************************
@@ -50,6 +57,10 @@ use crate::init::wifi;
 pub const NUM_LEDS: usize = 66;
 pub const LED_BUFFER_SIZE: usize = NUM_LEDS * 25;
 
+/// I2C bus shared by the RTC and the FRAM
+pub type I2cBus = embassy_sync::mutex::Mutex<NoopRawMutex, I2c<'static, Async>>;
+pub type SharedI2c = I2cDevice<'static, NoopRawMutex, I2c<'static, Async>>;
+
 static SD_DET: Mutex<RefCell<Option<Input>>> = Mutex::new(RefCell::new(None));
 
 #[panic_handler]
@@ -66,11 +77,11 @@ pub async fn hardware_init(
 ) -> (
     Uart<'static, Async>,
     Stack<'static>,
-    I2c<'static, Async>,
+    RTCClock,
     SmartLedsAdapterAsync<ConstChannelAccess<esp_hal::rmt::Tx, 0>, LED_BUFFER_SIZE>,
     GPIO21<'static>,
     GPIO0<'static>,
-    SDCardPersistence,
+    Storage,
 ) {
     let config = esp_hal::Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
@@ -95,6 +106,7 @@ pub async fn hardware_init(
     let uart_device = setup_uart(peripherals.UART1, peripherals.GPIO16, peripherals.GPIO17);
 
     let i2c_device = setup_i2c(peripherals.I2C0, peripherals.GPIO22, peripherals.GPIO23);
+    let i2c_bus: &'static I2cBus = make_static!(embassy_sync::mutex::Mutex::new(i2c_device));
 
     let sd_det_gpio = peripherals.GPIO0;
 
@@ -111,7 +123,22 @@ pub async fn hardware_init(
         OutputConfig::default(),
     );
 
-    let vol_mgr = setup_sdcard(spi_bus, sd_cs_pin);
+    // Read the time once before the storage is set up, so files written
+    // while recovering or migrating get the RTC time instead of the build time
+    let mut rtc = RTCClock::new(I2cDevice::new(i2c_bus)).await;
+    rtc.get_time().await;
+
+    let sd_card = setup_sdcard(spi_bus, sd_cs_pin);
+
+    let journal_start = FRAM_SIZE - JOURNAL_SIZE;
+    let fram = FramPersistence::new(Fram::new(I2cDevice::new(i2c_bus)), 0, journal_start).await;
+    let journal = FramJournal::new(
+        Fram::new(I2cDevice::new(i2c_bus)),
+        journal_start,
+        JOURNAL_SIZE,
+    )
+    .await;
+    let storage = setup_storage(sd_card, fram, journal).await;
 
     let buzzer_gpio = peripherals.GPIO21;
 
@@ -124,11 +151,11 @@ pub async fn hardware_init(
     (
         uart_device,
         stack,
-        i2c_device,
+        rtc,
         led,
         buzzer_gpio,
         sd_det_gpio,
-        vol_mgr,
+        storage,
     )
 }
 
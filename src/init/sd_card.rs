@@ -1,151 +1,665 @@
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
+use core::fmt::{Debug, Write};
 use embassy_time::Delay;
 use embedded_hal_bus::spi::ExclusiveDevice;
-use embedded_sdmmc::{SdCard, ShortFileName, TimeSource, Timestamp, VolumeIdx, VolumeManager};
+use embedded_sdmmc::{
+    BlockDevice, DirEntry, Directory, File, Mode, SdCard, ShortFileName, TimeSource, Timestamp,
+    VolumeIdx, VolumeManager,
+};
 use esp_hal::{Blocking, gpio::Output, spi::master::Spi};
-
-use crate::store::{AttendanceDay, IDMapping, day::Day, persistence::Persistence};
-
-pub struct DummyTimesource;
-
-impl TimeSource for DummyTimesource {
+use log::{error, info, warn};
+use serde::de::IgnoredAny;
+
+use crate::{
+    drivers::rtc,
+    store::{
+        AttendanceDay, IDMapping, ScanMode,
+        day::Day,
+        persistence::{Persistence, StorageError},
+        timezone::{LOCAL_TIMEZONE, civil_from_days},
+    },
+};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Larger files are not read, they would not fit into the heap.
+/// Far more than a day or the mapping of a school needs.
+const MAX_FILE_SIZE: u32 = 24 * 1024;
+
+/// Timestamps of files on the SD card in local time.
+/// This can't wait for the RTC on the shared I2C bus,
+/// so it uses the time of the last RTC access.
+pub struct RtcTimeSource;
+
+impl TimeSource for RtcTimeSource {
     fn get_timestamp(&self) -> Timestamp {
+        let local = LOCAL_TIMEZONE.to_local(rtc::cached_time());
+        let (year, month, day) = civil_from_days((local / SECONDS_PER_DAY) as i64);
+        let seconds = local % SECONDS_PER_DAY;
+
         Timestamp {
-            year_since_1970: 0,
-            zero_indexed_month: 0,
-            zero_indexed_day: 0,
-            hours: 0,
-            minutes: 0,
-            seconds: 0,
+            year_since_1970: (year - 1970).clamp(0, u8::MAX as i32) as u8,
+            zero_indexed_month: (month - 1) as u8,
+            zero_indexed_day: (day - 1) as u8,
+            hours: (seconds / 3600) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
         }
     }
 }
 
 pub type VolMgr = VolumeManager<
     SdCard<ExclusiveDevice<Spi<'static, Blocking>, Output<'static>, Delay>, Delay>,
-    DummyTimesource,
+    RtcTimeSource,
 >;
 
 pub fn setup_sdcard(spi_bus: Spi<'static, Blocking>, cs_pin: Output<'static>) -> SDCardPersistence {
     let spi_device = ExclusiveDevice::new(spi_bus, cs_pin, Delay).unwrap();
     let sd_card = SdCard::new(spi_device, Delay);
-    let vol_mgr = VolumeManager::new(sd_card, DummyTimesource);
+    let vol_mgr = VolumeManager::new(sd_card, RtcTimeSource);
 
     SDCardPersistence { vol_mgr }
 }
 
+/// Read the whole file.
+/// A single `read` only returns the data up to the end of the current block,
+/// so this keeps reading until the file is exhausted.
+/// Files larger than `MAX_FILE_SIZE` fail with `NotEnoughSpace`.
+fn read_to_end<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+    file: &mut File<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+) -> Result<Vec<u8>, embedded_sdmmc::Error<D::Error>>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    if file.length() > MAX_FILE_SIZE {
+        error!("File of {} bytes is too large to read", file.length());
+        return Err(embedded_sdmmc::Error::NotEnoughSpace);
+    }
+
+    let mut data = vec![0u8; file.length() as usize];
+    let mut filled = 0;
+
+    while filled < data.len() {
+        let read = file.read(&mut data[filled..])?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    data.truncate(filled);
+
+    Ok(data)
+}
+
+/// Read a whole file in `dir`, returns Ok(None) if it doesn't exist
+fn read_file<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+    dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    filename: ShortFileName,
+) -> Result<Option<Vec<u8>>, embedded_sdmmc::Error<D::Error>>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    let mut file = match dir.open_file_in_dir(filename, Mode::ReadOnly) {
+        Ok(file) => file,
+        Err(embedded_sdmmc::Error::NotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let data = read_to_end(&mut file)?;
+    file.close()?;
+
+    Ok(Some(data))
+}
+
+/// Create or replace a file with `data`.
+/// Data larger than `MAX_FILE_SIZE` fails with `NotEnoughSpace`, it could not be read again.
+fn write_file<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+    dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    filename: ShortFileName,
+    data: &[u8],
+) -> Result<(), embedded_sdmmc::Error<D::Error>>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    if data.len() > MAX_FILE_SIZE as usize {
+        error!("{} would be too large to read, not writing it", filename);
+        return Err(embedded_sdmmc::Error::NotEnoughSpace);
+    }
+
+    let mut file = dir.open_file_in_dir(filename, Mode::ReadWriteCreateOrTruncate)?;
+    file.write(data)?;
+    file.flush()?;
+    file.close()
+}
+
+/// Open a subdirectory of `dir`, creating it if it doesn't exist
+fn open_or_create_dir<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(
+    dir: &mut Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    name: ShortFileName,
+) -> Result<Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, embedded_sdmmc::Error<D::Error>>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    match dir.open_dir(name.clone()) {
+        Err(embedded_sdmmc::Error::NotFound) => {
+            dir.make_dir_in_dir(name.clone())?;
+            dir.open_dir(name)
+        }
+        result => result,
+    }
+}
+
+/// Subdirectories of `dir` named by a number with `digits` digits
+fn numbered_dirs<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+    dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    digits: usize,
+) -> Result<Vec<ShortFileName>, embedded_sdmmc::Error<D::Error>>
+where
+    D: BlockDevice,
+    T: TimeSource,
+{
+    let mut dirs = Vec::new();
+    dir.iterate_dir(|e| {
+        let name = e.name.base_name();
+
+        if e.attributes.is_directory()
+            && e.name.extension().is_empty()
+            && name.len() == digits
+            && name.iter().all(u8::is_ascii_digit)
+        {
+            dirs.push(e.name.clone());
+        }
+    })?;
+
+    Ok(dirs)
+}
+
+/// Same base name with another extension
+fn with_extension(filename: &ShortFileName, extension: &str) -> Option<ShortFileName> {
+    let basename = core::str::from_utf8(filename.base_name()).ok()?;
+    let mut name: heapless::String<12> = heapless::String::new();
+    name.push_str(basename).ok()?;
+    name.push('.').ok()?;
+    name.push_str(extension).ok()?;
+
+    ShortFileName::create_from_str(&name).ok()
+}
+
+/// Stores the mapping and the mode in the root directory and the days in
+/// `YYYY/MM/` subdirectories, so no directory grows unbounded.
 pub struct SDCardPersistence {
     vol_mgr: VolMgr,
 }
 
 impl SDCardPersistence {
     const MAPPING_FILENAME: &'static str = "MAPPING.JS";
+    const MODE_FILENAME: &'static str = "MODE.JS";
+    const FILE_EXTENSION: &'static str = Day::FILE_EXTENSION;
+    /// Day files named by days since 1970 in hex, the mapping and the mode
+    const LEGACY_EXTENSION: &'static str = Day::LEGACY_FILE_EXTENSION;
+    const TEMP_EXTENSION: &'static str = "TMP";
+    /// Unreadable day files are kept under this extension
+    const DISCARDED_EXTENSION: &'static str = "BAD";
+
+    /// Check if a card with a readable volume is inserted
+    pub fn is_available(&mut self) -> bool {
+        self.vol_mgr.open_volume(VolumeIdx(0)).is_ok()
+    }
 
+    /// Day file named by its date, e.g. `20251004.JSN`
     fn generate_filename(day: Day) -> ShortFileName {
+        let basename = day.to_date_string();
+        let mut filename: heapless::String<12> = heapless::String::new();
+        filename.push_str(&basename).unwrap();
+        filename.push('.').unwrap();
+        filename.push_str(Self::FILE_EXTENSION).unwrap();
+
+        ShortFileName::create_from_str(&filename).unwrap()
+    }
+
+    /// Day file name used by older firmware, e.g. `00004E3A.JS`
+    fn generate_legacy_filename(day: Day) -> ShortFileName {
         let basename = day.to_string();
         let mut filename: heapless::String<11> = heapless::String::new();
         filename.push_str(&basename).unwrap();
-        filename.push_str(".js").unwrap();
+        filename.push('.').unwrap();
+        filename.push_str(Self::LEGACY_EXTENSION).unwrap();
 
         ShortFileName::create_from_str(&filename).unwrap()
     }
-}
 
-impl Persistence for SDCardPersistence {
-    async fn load_day(&mut self, day: Day) -> Option<AttendanceDay> {
-        let mut vol_0 = self.vol_mgr.open_volume(VolumeIdx(0)).unwrap();
-        let mut root_dir = vol_0.open_root_dir().unwrap();
+    fn is_day_file(filename: &ShortFileName) -> bool {
+        let extension = filename.extension();
+        extension == Self::FILE_EXTENSION.as_bytes()
+            || extension == Self::LEGACY_EXTENSION.as_bytes()
+    }
 
-        let filename = Self::generate_filename(day);
-        let file = root_dir.open_file_in_dir(filename, embedded_sdmmc::Mode::ReadOnly);
+    /// Name of the file a temporary file of `write_atomic` replaces
+    fn target_filename(temp_filename: &ShortFileName) -> Option<ShortFileName> {
+        let basename = core::str::from_utf8(temp_filename.base_name()).ok()?;
 
-        if file.is_err() {
-            return None;
+        if Day::from_date_str(basename).is_ok() {
+            with_extension(temp_filename, Self::FILE_EXTENSION)
+        } else {
+            with_extension(temp_filename, Self::LEGACY_EXTENSION)
         }
+    }
+
+    /// Names of the year and month directories of a day
+    fn generate_dirnames(day: Day) -> (ShortFileName, ShortFileName) {
+        let (year, month, _) = day.to_ymd();
+
+        let mut year_name: heapless::String<8> = heapless::String::new();
+        write!(year_name, "{:04}", year).unwrap();
+        let mut month_name: heapless::String<2> = heapless::String::new();
+        write!(month_name, "{:02}", month).unwrap();
+
+        (
+            ShortFileName::create_from_str(&year_name).unwrap(),
+            ShortFileName::create_from_str(&month_name).unwrap(),
+        )
+    }
+
+    /// Write a file so that a power loss never leaves it truncated.
+    /// The FAT driver can't rename files, so the data goes to a temporary file first
+    /// and is then copied over the original. The temporary file is only removed
+    /// once the original is complete, `recover` finishes an interrupted copy.
+    fn write_atomic<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+        dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        filename: ShortFileName,
+        data: &[u8],
+    ) -> Result<(), embedded_sdmmc::Error<D::Error>>
+    where
+        D: BlockDevice,
+        T: TimeSource,
+    {
+        let temp_filename = with_extension(&filename, Self::TEMP_EXTENSION).unwrap();
+
+        write_file(dir, temp_filename.clone(), data)?;
+        write_file(dir, filename, data)?;
+        dir.delete_file_in_dir(temp_filename)
+    }
 
-        let mut open_file = file.unwrap();
+    /// Copy a file to one with the discarded extension and delete it.
+    /// Returns Ok(false) if the file doesn't exist.
+    fn discard_file<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+        dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        filename: ShortFileName,
+    ) -> Result<bool, embedded_sdmmc::Error<D::Error>>
+    where
+        D: BlockDevice,
+        T: TimeSource,
+    {
+        let Some(data) = read_file(dir, filename.clone())? else {
+            return Ok(false);
+        };
+
+        let discarded = with_extension(&filename, Self::DISCARDED_EXTENSION).unwrap();
+        warn!("Moving {} to {}", filename, discarded);
+        write_file(dir, discarded, &data)?;
+        dir.delete_file_in_dir(filename)?;
+
+        Ok(true)
+    }
 
-        let mut read_buffer: [u8; 1024] = [0; 1024];
-        let read = open_file.read(&mut read_buffer).unwrap();
-        open_file.close().unwrap();
+    /// Handle temporary files in `dir` left behind by a power loss during `write_atomic`.
+    /// A complete temporary file replaces its original, an incomplete one is discarded.
+    fn recover_dir<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+        dir: &mut Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    ) -> Result<(), embedded_sdmmc::Error<D::Error>>
+    where
+        D: BlockDevice,
+        T: TimeSource,
+    {
+        let mut temp_files: Vec<ShortFileName> = Vec::new();
+        dir.iterate_dir(|e| {
+            if e.name.extension() == Self::TEMP_EXTENSION.as_bytes() {
+                temp_files.push(e.name.clone());
+            }
+        })?;
+
+        for temp_filename in temp_files {
+            let data = match read_file(dir, temp_filename.clone()) {
+                Ok(data) => data.unwrap_or_default(),
+                // Can't be complete, nothing that large is written
+                Err(embedded_sdmmc::Error::NotEnoughSpace) => Vec::new(),
+                Err(e) => return Err(e),
+            };
+
+            // Only the JSON is checked, the content is validated when it is loaded
+            let complete = serde_json::from_slice::<IgnoredAny>(&data).is_ok();
+
+            match Self::target_filename(&temp_filename) {
+                Some(filename) if complete => {
+                    info!("Restoring {} from interrupted write", filename);
+                    write_file(dir, filename, &data)?;
+                }
+                _ => warn!("Discarding incomplete {}", temp_filename),
+            }
 
-        let day: AttendanceDay = serde_json::from_slice(&read_buffer[..read]).unwrap();
+            dir.delete_file_in_dir(temp_filename)?;
+        }
 
-        Some(day)
+        Ok(())
     }
 
-    async fn save_day(&mut self, day: Day, data: &AttendanceDay) {
-        let mut vol_0 = self.vol_mgr.open_volume(VolumeIdx(0)).unwrap();
-        let mut root_dir = vol_0.open_root_dir().unwrap();
+    /// Finish writes that were interrupted by a power loss
+    pub fn recover(&mut self) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
 
-        let filename = Self::generate_filename(day);
+        Self::recover_dir(&mut root_dir).map_err(storage_error)?;
 
-        let mut file = root_dir
-            .open_file_in_dir(filename, embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)
-            .unwrap();
+        for year in numbered_dirs(&mut root_dir, 4).map_err(storage_error)? {
+            let mut year_dir = root_dir.open_dir(year).map_err(storage_error)?;
 
-        file.write(&serde_json::to_vec(data).unwrap()).unwrap();
+            for month in numbered_dirs(&mut year_dir, 2).map_err(storage_error)? {
+                let mut month_dir = year_dir.open_dir(month).map_err(storage_error)?;
+                Self::recover_dir(&mut month_dir).map_err(storage_error)?;
+            }
+        }
 
-        file.flush().unwrap();
-        file.close().unwrap();
+        Ok(())
     }
 
-    async fn load_mapping(&mut self) -> Option<crate::store::IDMapping> {
-        let mut vol_0 = self.vol_mgr.open_volume(VolumeIdx(0)).unwrap();
-        let mut root_dir = vol_0.open_root_dir().unwrap();
+    /// Move day files written before the directory layout from the root
+    /// into their year and month directories
+    pub fn migrate(&mut self) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let mut files: Vec<(Day, ShortFileName)> = Vec::new();
+        root_dir
+            .iterate_dir(|e| {
+                if !Self::is_day_file(&e.name) {
+                    return;
+                }
+
+                if let Ok(day) = e.name.clone().try_into() {
+                    files.push((day, e.name.clone()));
+                }
+            })
+            .map_err(storage_error)?;
+
+        if files.is_empty() {
+            return Ok(());
+        }
+        info!("Moving {} day files into directories", files.len());
+
+        for (day, old_filename) in files {
+            let filename = Self::generate_filename(day);
+            let (year, month) = Self::generate_dirnames(day);
+
+            let mut year_dir = open_or_create_dir(&mut root_dir, year).map_err(storage_error)?;
+            let mut month_dir = open_or_create_dir(&mut year_dir, month).map_err(storage_error)?;
+
+            // A file in the directory was saved after an interrupted migration.
+            // It was merged with the file in the root and is the newer one.
+            match month_dir.find_directory_entry(filename.clone()) {
+                Ok(_) => {}
+                Err(embedded_sdmmc::Error::NotFound) => {
+                    if let Some(data) =
+                        read_file(&mut root_dir, old_filename.clone()).map_err(storage_error)?
+                    {
+                        Self::write_atomic(&mut month_dir, filename, &data)
+                            .map_err(storage_error)?;
+                    }
+                }
+                Err(e) => return Err(storage_error(e)),
+            }
+
+            root_dir
+                .delete_file_in_dir(old_filename)
+                .map_err(storage_error)?;
+        }
 
-        let file =
-            root_dir.open_file_in_dir(Self::MAPPING_FILENAME, embedded_sdmmc::Mode::ReadOnly);
+        Ok(())
+    }
+}
 
-        if file.is_err() {
-            return None;
+impl Persistence for SDCardPersistence {
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let (year, month) = Self::generate_dirnames(day);
+
+        let mut data = match root_dir
+            .open_dir(year)
+            .and_then(|year_dir| year_dir.open_dir(month))
+        {
+            Ok(mut month_dir) => {
+                read_file(&mut month_dir, Self::generate_filename(day)).map_err(storage_error)?
+            }
+            Err(embedded_sdmmc::Error::NotFound) => None,
+            Err(e) => return Err(storage_error(e)),
+        };
+
+        // The file is still in the root if the migration didn't finish
+        if data.is_none() {
+            data = read_file(&mut root_dir, Self::generate_legacy_filename(day))
+                .map_err(storage_error)?;
         }
 
-        let mut open_file = file.unwrap();
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        let day: AttendanceDay =
+            serde_json::from_slice(&data).map_err(|_| StorageError::Serialization)?;
+
+        Ok(Some(day))
+    }
+
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let json = serde_json::to_vec(data).map_err(|_| StorageError::Serialization)?;
+
+        let (year, month) = Self::generate_dirnames(day);
+        let mut year_dir = open_or_create_dir(&mut root_dir, year).map_err(storage_error)?;
+        let mut month_dir = open_or_create_dir(&mut year_dir, month).map_err(storage_error)?;
+
+        Self::write_atomic(&mut month_dir, Self::generate_filename(day), &json)
+            .map_err(storage_error)
+    }
+
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let filename = ShortFileName::create_from_str(Self::MAPPING_FILENAME).unwrap();
+        let Some(data) = read_file(&mut root_dir, filename).map_err(storage_error)? else {
+            return Ok(None);
+        };
 
-        let mut read_buffer: [u8; 1024] = [0; 1024];
-        let read = open_file.read(&mut read_buffer).unwrap();
-        open_file.close().unwrap();
+        let mapping: IDMapping =
+            serde_json::from_slice(&data).map_err(|_| StorageError::Serialization)?;
 
-        let mapping: IDMapping = serde_json::from_slice(&read_buffer[..read]).unwrap();
+        Ok(Some(mapping))
+    }
+
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let json = serde_json::to_vec(data).map_err(|_| StorageError::Serialization)?;
+        let filename = ShortFileName::create_from_str(Self::MAPPING_FILENAME).unwrap();
 
-        Some(mapping)
+        Self::write_atomic(&mut root_dir, filename, &json).map_err(storage_error)
     }
 
-    async fn save_mapping(&mut self, data: &crate::store::IDMapping) {
-        let mut vol_0 = self.vol_mgr.open_volume(VolumeIdx(0)).unwrap();
-        let mut root_dir = vol_0.open_root_dir().unwrap();
+    /// The file is moved to `MAPPING.BAD`
+    async fn discard_mapping(&mut self) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let filename = ShortFileName::create_from_str(Self::MAPPING_FILENAME).unwrap();
+        Self::discard_file(&mut root_dir, filename).map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
 
-        let mut file = root_dir
-            .open_file_in_dir(
-                Self::MAPPING_FILENAME,
-                embedded_sdmmc::Mode::ReadWriteCreateOrTruncate,
-            )
-            .unwrap();
+        let filename = ShortFileName::create_from_str(Self::MODE_FILENAME).unwrap();
+        let Some(data) = read_file(&mut root_dir, filename).map_err(storage_error)? else {
+            return Ok(None);
+        };
 
-        file.write(&serde_json::to_vec(data).unwrap()).unwrap();
+        let mode: ScanMode =
+            serde_json::from_slice(&data).map_err(|_| StorageError::Serialization)?;
 
-        file.flush().unwrap();
-        file.close().unwrap();
+        Ok(Some(mode))
     }
 
-    async fn list_days(&mut self) -> Vec<Day> {
-        let mut vol_0 = self.vol_mgr.open_volume(VolumeIdx(0)).unwrap();
-        let mut root_dir = vol_0.open_root_dir().unwrap();
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let json = serde_json::to_vec(&mode).map_err(|_| StorageError::Serialization)?;
+        let filename = ShortFileName::create_from_str(Self::MODE_FILENAME).unwrap();
 
-        let mut days_dir = root_dir.open_dir(".").unwrap();
+        Self::write_atomic(&mut root_dir, filename, &json).map_err(storage_error)
+    }
+
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
 
         let mut days: Vec<Day> = Vec::new();
-        days_dir
-            .iterate_dir(|e| {
-                let filename = e.name.clone();
+        let mut collect_days = |e: &DirEntry| {
+            if !Self::is_day_file(&e.name) {
+                return;
+            }
+
+            if let Ok(day) = e.name.clone().try_into() {
+                days.push(day);
+            }
+        };
+
+        // Leftovers of an interrupted migration
+        root_dir
+            .iterate_dir(&mut collect_days)
+            .map_err(storage_error)?;
+
+        for year in numbered_dirs(&mut root_dir, 4).map_err(storage_error)? {
+            let mut year_dir = root_dir.open_dir(year).map_err(storage_error)?;
+
+            for month in numbered_dirs(&mut year_dir, 2).map_err(storage_error)? {
+                let mut month_dir = year_dir.open_dir(month).map_err(storage_error)?;
+                month_dir
+                    .iterate_dir(&mut collect_days)
+                    .map_err(storage_error)?;
+            }
+        }
+
+        days.sort_unstable();
+        days.dedup();
+
+        Ok(days)
+    }
 
-                if let Ok(day) = filename.try_into() {
-                    days.push(day);
+    /// The file that `load_day` reads is moved to `.BAD`
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError> {
+        let mut vol_0 = self
+            .vol_mgr
+            .open_volume(VolumeIdx(0))
+            .map_err(storage_error)?;
+        let mut root_dir = vol_0.open_root_dir().map_err(storage_error)?;
+
+        let (year, month) = Self::generate_dirnames(day);
+
+        match root_dir
+            .open_dir(year)
+            .and_then(|year_dir| year_dir.open_dir(month))
+        {
+            Ok(mut month_dir) => {
+                if Self::discard_file(&mut month_dir, Self::generate_filename(day))
+                    .map_err(storage_error)?
+                {
+                    return Ok(());
                 }
-            })
-            .unwrap();
+            }
+            Err(embedded_sdmmc::Error::NotFound) => {}
+            Err(e) => return Err(storage_error(e)),
+        }
+
+        Self::discard_file(&mut root_dir, Self::generate_legacy_filename(day))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<bool, StorageError> {
+        // The card has to go through the SPI init sequence again
+        self.vol_mgr.device(|card| card.mark_card_uninit());
 
-        days
+        if let Err(e) = self.recover() {
+            error!("Failed to recover interrupted writes: {:?}", e);
+        }
+        if let Err(e) = self.migrate() {
+            error!("Failed to move day files into directories: {:?}", e);
+        }
+        Ok(false)
+    }
+}
+
+fn storage_error<E: Debug>(e: embedded_sdmmc::Error<E>) -> StorageError {
+    error!("SD card error: {:?}", e);
+
+    match e {
+        embedded_sdmmc::Error::DeviceError(_) => StorageError::Device,
+        embedded_sdmmc::Error::FormatError(_)
+        | embedded_sdmmc::Error::NoSuchVolume
+        | embedded_sdmmc::Error::Unsupported
+        | embedded_sdmmc::Error::BadCluster
+        | embedded_sdmmc::Error::UnterminatedFatChain => StorageError::Filesystem,
+        embedded_sdmmc::Error::NotEnoughSpace | embedded_sdmmc::Error::DiskFull => {
+            StorageError::Full
+        }
+        _ => StorageError::Io,
     }
 }
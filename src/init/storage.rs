@@ -0,0 +1,247 @@
+use alloc::vec::Vec;
+use log::{error, info, warn};
+
+use crate::{
+    init::{
+        fram::{FramJournal, FramPersistence},
+        sd_card::SDCardPersistence,
+    },
+    store::{
+        AttendanceDay, IDMapping, ScanMode,
+        day::Day,
+        persistence::{JournalEntry, Persistence, StorageError},
+    },
+};
+
+/// Storage selected at boot.
+/// The SD card is used if one is inserted, otherwise the on-board FRAM.
+/// A card inserted later replaces the FRAM, the data in the FRAM is copied onto it.
+/// Scans are journaled in the FRAM before they are written to the backend.
+pub struct Storage {
+    sd_card: SDCardPersistence,
+    backend: Backend,
+    journal: Option<FramJournal>,
+}
+
+enum Backend {
+    SdCard,
+    Fram(FramPersistence),
+}
+
+pub async fn setup_storage(
+    mut sd_card: SDCardPersistence,
+    fram: Result<FramPersistence, StorageError>,
+    journal: Result<FramJournal, StorageError>,
+) -> Storage {
+    let journal = match journal {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            warn!("FRAM journal is unavailable: {:?}", e);
+            None
+        }
+    };
+
+    let sd_card_available = sd_card.is_available();
+    if sd_card_available {
+        info!("Using SD card as storage");
+        if let Err(e) = sd_card.recover() {
+            error!("Failed to recover interrupted writes: {:?}", e);
+        }
+        if let Err(e) = sd_card.migrate() {
+            error!("Failed to move day files into directories: {:?}", e);
+        }
+    }
+
+    let backend = match fram {
+        Ok(fram) if !sd_card_available => {
+            info!("No SD card found, using FRAM as storage");
+            Backend::Fram(fram)
+        }
+        Ok(mut fram) => {
+            // Left from a time without SD card, it is copied again on the next boot if this fails
+            if let Err(e) = copy_to_sd_card(&mut fram, &mut sd_card).await {
+                error!("Failed to copy the FRAM data to the SD card: {:?}", e);
+            }
+            Backend::SdCard
+        }
+        Err(e) => {
+            if !sd_card_available {
+                warn!("No SD card found and FRAM is unavailable: {:?}", e);
+            }
+            Backend::SdCard
+        }
+    };
+
+    Storage {
+        sd_card,
+        backend,
+        journal,
+    }
+}
+
+/// Copy the data stored in the FRAM onto the SD card and empty the FRAM.
+/// Days are merged with the ones on the card and removed from the FRAM one by one,
+/// so a copy that is interrupted and repeated does not count them twice.
+/// Names and the mode from the FRAM replace the ones on the card, as they are newer.
+async fn copy_to_sd_card(
+    fram: &mut FramPersistence,
+    sd_card: &mut SDCardPersistence,
+) -> Result<(), StorageError> {
+    let days = fram.list_days().await?;
+    let fram_mapping = fram.load_mapping().await?;
+    let mode = fram.load_mode().await?;
+
+    if days.is_empty() && fram_mapping.is_none() && mode.is_none() {
+        return Ok(());
+    }
+    info!("Copying {} days from the FRAM to the SD card", days.len());
+
+    if let Some(fram_mapping) = fram_mapping {
+        let mut mapping = sd_card.load_mapping().await?.unwrap_or_default();
+        for (id, name) in fram_mapping.iter() {
+            mapping.add_mapping(*id, name.clone());
+        }
+        sd_card.save_mapping(&mapping).await?;
+    }
+
+    if let Some(mode) = mode {
+        sd_card.save_mode(mode).await?;
+    }
+
+    for day in days {
+        match fram.load_day(day).await {
+            Ok(Some(mut data)) => {
+                if let Some(stored) = sd_card.load_day(day).await? {
+                    data.merge(&stored);
+                }
+                sd_card.save_day(day, &data).await?;
+            }
+            Ok(None) => {}
+            Err(StorageError::Serialization) => {
+                error!(
+                    "Day {} in the FRAM is corrupt, it is not copied",
+                    day.to_date_string()
+                );
+            }
+            Err(e) => return Err(e),
+        }
+        fram.discard_day(day).await?;
+    }
+
+    fram.format().await
+}
+
+impl Persistence for Storage {
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.load_day(day).await,
+            Backend::Fram(fram) => fram.load_day(day).await,
+        }
+    }
+
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.save_day(day, data).await,
+            Backend::Fram(fram) => fram.save_day(day, data).await,
+        }
+    }
+
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.list_days().await,
+            Backend::Fram(fram) => fram.list_days().await,
+        }
+    }
+
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.discard_day(day).await,
+            Backend::Fram(fram) => fram.discard_day(day).await,
+        }
+    }
+
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.load_mapping().await,
+            Backend::Fram(fram) => fram.load_mapping().await,
+        }
+    }
+
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.save_mapping(data).await,
+            Backend::Fram(fram) => fram.save_mapping(data).await,
+        }
+    }
+
+    async fn discard_mapping(&mut self) -> Result<(), StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.discard_mapping().await,
+            Backend::Fram(fram) => fram.discard_mapping().await,
+        }
+    }
+
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.load_mode().await,
+            Backend::Fram(fram) => fram.load_mode().await,
+        }
+    }
+
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        match &mut self.backend {
+            Backend::SdCard => self.sd_card.save_mode(mode).await,
+            Backend::Fram(fram) => fram.save_mode(mode).await,
+        }
+    }
+
+    /// Switches from the FRAM to the SD card once one is inserted.
+    /// Returns Err while the data can only be stored in the FRAM.
+    async fn reconnect(&mut self) -> Result<bool, StorageError> {
+        self.sd_card.reconnect().await?;
+
+        let Backend::Fram(fram) = &mut self.backend else {
+            return Ok(false);
+        };
+        if !self.sd_card.is_available() {
+            warn!("No SD card found, the data stays in the FRAM");
+            return Err(StorageError::Device);
+        }
+
+        copy_to_sd_card(fram, &mut self.sd_card).await?;
+        info!("Switched storage from FRAM to SD card");
+        self.backend = Backend::SdCard;
+        Ok(true)
+    }
+
+    async fn journal_append(&mut self, entry: JournalEntry) -> Result<(), StorageError> {
+        match &mut self.journal {
+            Some(journal) => journal.append(entry).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn journal_entries(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+        match &mut self.journal {
+            Some(journal) => journal.entries().await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn journal_clear(&mut self) -> Result<(), StorageError> {
+        match &mut self.journal {
+            Some(journal) => journal.clear().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn journal_retain(
+        &mut self,
+        keep: impl Fn(&JournalEntry) -> bool,
+    ) -> Result<(), StorageError> {
+        match &mut self.journal {
+            Some(journal) => journal.retain(keep).await,
+            None => Ok(()),
+        }
+    }
+}
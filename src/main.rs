@@ -4,13 +4,14 @@
 #![feature(impl_trait_in_assoc_type)]
 
 use alloc::rc::Rc;
+use core::sync::atomic::{AtomicBool, AtomicU32};
 use embassy_executor::Spawner;
 use embassy_net::Stack;
 use embassy_sync::{
     blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
     mutex::Mutex,
     pubsub::{
-        PubSubChannel, Publisher, Subscriber,
+        ImmediatePublisher, PubSubChannel, Publisher, Subscriber,
         WaitResult::{Lagged, Message},
     },
     signal::Signal,
@@ -18,62 +19,83 @@ use embassy_sync::{
 use embassy_time::{Duration, Timer};
 use esp_hal::gpio::Input;
 use esp_hal::{gpio::InputConfig, peripherals};
-use log::{debug, info};
+use log::{debug, error, info};
 use static_cell::make_static;
 
 extern crate alloc;
 
 use crate::{
-    init::sd_card::SDCardPersistence,
-    store::{IDStore, day::Day, tally_id::TallyID},
-    webserver::start_webserver,
+    init::storage::Storage,
+    reader::hold_off::DEFAULT_WINDOW_MS,
+    store::{IDStore, ScanResult, day::Day, tally_id::TallyID},
+    webserver::{ScanEvent, start_webserver},
 };
 
 mod drivers;
 mod feedback;
 mod init;
-mod store;
 mod webserver;
 
+use anwesenheit_reader as reader;
+use anwesenheit_store as store;
+
 static FEEDBACK_STATE: Signal<CriticalSectionRawMutex, feedback::FeedbackState> = Signal::new();
 
 type TallyChannel = PubSubChannel<NoopRawMutex, TallyID, 8, 2, 1>;
 type TallyPublisher = Publisher<'static, NoopRawMutex, TallyID, 8, 2, 1>;
 type TallySubscriber = Subscriber<'static, NoopRawMutex, TallyID, 8, 2, 1>;
-type UsedStore = IDStore<SDCardPersistence>;
+type ScanChannel = PubSubChannel<NoopRawMutex, ScanEvent, 8, 2, 1>;
+type ScanPublisher = ImmediatePublisher<'static, NoopRawMutex, ScanEvent, 8, 2, 1>;
+type ScanSubscriber = Subscriber<'static, NoopRawMutex, ScanEvent, 8, 2, 1>;
+type UsedStore = IDStore<Storage>;
 
 #[esp_hal_embassy::main]
 async fn main(mut spawner: Spawner) {
-    let (uart_device, stack, _i2c, _led, buzzer_gpio, sd_det_gpio, persistence_layer) =
+    let (uart_device, stack, mut rtc, _led, buzzer_gpio, sd_det_gpio, persistence_layer) =
         init::hardware::hardware_init(&mut spawner).await;
 
     info!("Starting up...");
 
-    let mut rtc = drivers::rtc::RTCClock::new(_i2c).await;
-
-    let store: UsedStore = IDStore::new_from_storage(persistence_layer).await;
+    let today: Day = rtc.get_time().await.into();
+    let rtc = Rc::new(Mutex::new(rtc));
+    let store: UsedStore = IDStore::new_from_storage(persistence_layer, today).await;
     let shared_store = Rc::new(Mutex::new(store));
 
     let chan: &'static mut TallyChannel = make_static!(PubSubChannel::new());
     let publisher: TallyPublisher = chan.publisher().unwrap();
     let mut sub: TallySubscriber = chan.subscriber().unwrap();
+    let scans: &'static ScanChannel = make_static!(PubSubChannel::new());
+    let scan_publisher: ScanPublisher = scans.immediate_publisher();
+    let hold_off_ms: &'static AtomicU32 = make_static!(AtomicU32::new(DEFAULT_WINDOW_MS));
+    // Off by default, because weak coupling produces bad frames next to good ones
+    let signal_rejected: &'static AtomicBool = make_static!(AtomicBool::new(false));
 
     wait_for_stack_up(stack).await;
 
-    start_webserver(&mut spawner, stack, shared_store.clone(), chan);
+    start_webserver(
+        &mut spawner,
+        stack,
+        shared_store.clone(),
+        rtc.clone(),
+        scans,
+        hold_off_ms,
+        signal_rejected,
+    );
 
     /****************************** Spawning tasks ***********************************/
     debug!("spawing NFC reader task...");
     spawner.must_spawn(drivers::nfc_reader::rfid_reader_task(
         uart_device,
         publisher,
+        hold_off_ms,
+        signal_rejected,
     ));
 
     debug!("spawing feedback task..");
     spawner.must_spawn(feedback::feedback_task(_led, buzzer_gpio));
 
     debug!("spawn sd detect task");
-    spawner.must_spawn(sd_detect_task(sd_det_gpio));
+    spawner.must_spawn(sd_detect_task(sd_det_gpio, shared_store.clone()));
     /******************************************************************************/
 
     debug!("everything spawned");
@@ -86,19 +108,42 @@ async fn main(mut spawner: Spawner) {
             Message(msg) => {
                 debug!("Got message: {msg:?}");
 
-                let day: Day = rtc.get_time().await.into();
-                let added = shared_store.lock().await.add_id(msg, day).await;
-
-                if added {
-                    FEEDBACK_STATE.signal(feedback::FeedbackState::Ack);
+                let now = rtc.lock().await.get_time().await;
+                let (result, known) = {
+                    let mut store = shared_store.lock().await;
+                    let result = store.add_id(msg, now).await;
+                    (result, store.is_known(&msg))
+                };
+
+                match &result {
+                    Ok(ScanResult::CheckedOut { present_secs }) => info!(
+                        "{msg} checked out, present for {} min today",
+                        present_secs / 60
+                    ),
+                    Err(e) => error!("Failed to persist scan of {msg}: {e:?}"),
+                    _ => {}
+                }
+                if !known {
+                    info!("{msg} has no name yet");
                 }
+
+                FEEDBACK_STATE.signal(feedback::FeedbackState::for_scan(&result, known));
+
+                // Never wait for the web UI, a slow client only misses old scans
+                scan_publisher.publish_immediate(ScanEvent {
+                    id: msg,
+                    already_present: matches!(result, Ok(ScanResult::AlreadyPresent)),
+                });
             }
         }
     }
 }
 
 #[embassy_executor::task]
-async fn sd_detect_task(sd_det_gpio: peripherals::GPIO0<'static>) {
+async fn sd_detect_task(
+    sd_det_gpio: peripherals::GPIO0<'static>,
+    store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
+) {
     let mut sd_det = Input::new(sd_det_gpio, InputConfig::default());
     sd_det.wait_for(esp_hal::gpio::Event::AnyEdge).await;
 
@@ -106,8 +151,15 @@ async fn sd_detect_task(sd_det_gpio: peripherals::GPIO0<'static>) {
         sd_det.wait_for_any_edge().await;
         {
             if sd_det.is_high() {
-                FEEDBACK_STATE.signal(feedback::FeedbackState::Ack);
                 debug!("card insert");
+                // Write everything that was scanned while the card was missing
+                match store.lock().await.flush().await {
+                    Ok(()) => FEEDBACK_STATE.signal(feedback::FeedbackState::Ack),
+                    Err(e) => {
+                        error!("Failed to write pending data to SD card: {e:?}");
+                        FEEDBACK_STATE.signal(feedback::FeedbackState::Error);
+                    }
+                }
             }
             //card is not insert on low
             else {
@@ -1,12 +1,15 @@
+use core::sync::atomic::Ordering;
+
+use log::{error, info};
 use picoserve::{
     extract::{Json, State},
-    response::{self, IntoResponse},
+    response::{self, IntoResponse, StatusCode, chunked::ChunkedResponse},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    store::{Name, tally_id::TallyID},
-    webserver::{app::AppState, sse::IDEvents},
+    store::{Name, ScanMode, tally_id::TallyID},
+    webserver::{app::AppState, csv::AttendanceCsv, sse::IDEvents},
 };
 
 #[derive(Deserialize)]
@@ -15,6 +18,28 @@ pub struct NewMapping {
     name: Name,
 }
 
+/// Unix timestamp in seconds
+#[derive(Serialize, Deserialize)]
+pub struct Time {
+    timestamp: u64,
+}
+
+/// Window in which repeated reads of a card are ignored,
+/// and whether unreadable frames are signalled
+#[derive(Serialize, Deserialize)]
+pub struct HoldOff {
+    millis: u32,
+    signal_rejected: bool,
+}
+
+/// Longer windows would swallow real scans of the same card
+const MAX_HOLD_OFF_MS: u32 = 60_000;
+
+/// The RTC only counts the years 2000 to 2099.
+/// 2000-01-01 00:00 UTC and 2100-01-01 00:00 UTC as Unix timestamps.
+const MIN_TIMESTAMP: u64 = 946_684_800;
+const MAX_TIMESTAMP: u64 = 4_102_444_800;
+
 // struct MappingWrapper(IDMapping);
 //
 // impl Serialize for MappingWrapper {
@@ -41,9 +66,87 @@ pub async fn add_mapping(
     Json(data): Json<NewMapping>,
 ) -> impl IntoResponse {
     let mut store = state.store.lock().await;
-    store.mapping.add_mapping(data.id, data.name);
+    match store.add_mapping(data.id, data.name).await {
+        Ok(()) => (StatusCode::OK, ""),
+        Err(e) => {
+            error!("Failed to persist mapping for {}: {:?}", data.id, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save mapping")
+        }
+    }
+}
+
+pub async fn get_unknown(State(state): State<AppState>) -> impl IntoResponse {
+    let store = state.store.lock().await;
+    response::Json(store.unknown_ids().to_vec())
 }
 
 pub async fn get_idevent(State(state): State<AppState>) -> impl IntoResponse {
     response::EventStream(IDEvents(state.chan.subscriber().unwrap()))
 }
+
+pub async fn get_csv(State(state): State<AppState>) -> impl IntoResponse {
+    let mut store = state.store.lock().await;
+    ChunkedResponse::new(AttendanceCsv::collect(&mut store).await)
+}
+
+pub async fn get_mode(State(state): State<AppState>) -> impl IntoResponse {
+    let store = state.store.lock().await;
+    response::Json(store.mode())
+}
+
+pub async fn set_mode(
+    State(state): State<AppState>,
+    Json(mode): Json<ScanMode>,
+) -> impl IntoResponse {
+    let mut store = state.store.lock().await;
+    match store.set_mode(mode).await {
+        Ok(()) => (StatusCode::OK, ""),
+        Err(e) => {
+            // The mode is used anyway, it is written once the storage is back
+            error!("Failed to persist mode: {:?}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save mode")
+        }
+    }
+}
+
+pub async fn get_time(State(state): State<AppState>) -> impl IntoResponse {
+    let timestamp = state.rtc.lock().await.get_time().await;
+    response::Json(Time { timestamp })
+}
+
+pub async fn set_time(State(state): State<AppState>, Json(time): Json<Time>) -> impl IntoResponse {
+    if !(MIN_TIMESTAMP..MAX_TIMESTAMP).contains(&time.timestamp) {
+        return (StatusCode::BAD_REQUEST, "Time outside of 2000 to 2099");
+    }
+
+    match state.rtc.lock().await.set_time(time.timestamp).await {
+        Ok(()) => (StatusCode::OK, ""),
+        Err(()) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set time"),
+    }
+}
+
+pub async fn get_hold_off(State(state): State<AppState>) -> impl IntoResponse {
+    response::Json(HoldOff {
+        millis: state.hold_off_ms.load(Ordering::Relaxed),
+        signal_rejected: state.signal_rejected.load(Ordering::Relaxed),
+    })
+}
+
+pub async fn set_hold_off(
+    State(state): State<AppState>,
+    Json(hold_off): Json<HoldOff>,
+) -> impl IntoResponse {
+    if hold_off.millis > MAX_HOLD_OFF_MS {
+        return (StatusCode::BAD_REQUEST, "Hold-off window too long");
+    }
+
+    info!(
+        "Hold-off window set to {} ms, rejected frames signalled: {}",
+        hold_off.millis, hold_off.signal_rejected
+    );
+    state.hold_off_ms.store(hold_off.millis, Ordering::Relaxed);
+    state
+        .signal_rejected
+        .store(hold_off.signal_rejected, Ordering::Relaxed);
+    (StatusCode::OK, "")
+}
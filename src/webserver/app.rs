@@ -1,11 +1,16 @@
 use alloc::rc::Rc;
+use core::sync::atomic::{AtomicBool, AtomicU32};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
 use picoserve::{AppWithStateBuilder, routing::get};
 
 use crate::{
-    TallyChannel, UsedStore,
+    ScanChannel, UsedStore,
+    drivers::rtc::RTCClock,
     webserver::{
-        api::{add_mapping, get_idevent, get_mapping},
+        api::{
+            add_mapping, get_csv, get_hold_off, get_idevent, get_mapping, get_mode, get_time,
+            get_unknown, set_hold_off, set_mode, set_time,
+        },
         assets::Assets,
     },
 };
@@ -13,7 +18,12 @@ use crate::{
 #[derive(Clone)]
 pub struct AppState {
     pub store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
-    pub chan: &'static TallyChannel,
+    pub rtc: Rc<Mutex<CriticalSectionRawMutex, RTCClock>>,
+    pub chan: &'static ScanChannel,
+    /// Window in which repeated reads of a card are ignored, in milliseconds
+    pub hold_off_ms: &'static AtomicU32,
+    /// Whether frames rejected by the reader give a Nack
+    pub signal_rejected: &'static AtomicBool,
 }
 
 pub struct AppProps;
@@ -25,6 +35,11 @@ impl AppWithStateBuilder for AppProps {
     fn build_app(self) -> picoserve::Router<Self::PathRouter, AppState> {
         picoserve::Router::from_service(Assets)
             .route("/api/mapping", get(get_mapping).post(add_mapping))
+            .route("/api/unknown", get(get_unknown))
             .route("/api/idevent", get(get_idevent))
+            .route("/api/csv", get(get_csv))
+            .route("/api/mode", get(get_mode).post(set_mode))
+            .route("/api/time", get(get_time).post(set_time))
+            .route("/api/holdoff", get(get_hold_off).post(set_hold_off))
     }
 }
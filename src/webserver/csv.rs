@@ -0,0 +1,145 @@
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::fmt::Write;
+use log::warn;
+use picoserve::response::chunked::{ChunkWriter, Chunks, ChunksWritten};
+
+use crate::store::{IDStore, Name, day::Day, persistence::Persistence, tally_id::TallyID};
+
+/// Attendance table that gets streamed as CSV.
+///
+/// Only the attendance matrix (one bit per person and day) and the presence
+/// times are kept in memory, the CSV itself is generated and sent line by line.
+/// A day is marked with `x`, or with the presence as `h:mm` if the ID checked in and out.
+/// Check-ins without a check-out are not counted.
+pub struct AttendanceCsv {
+    days: Vec<Day>,
+    rows: BTreeMap<TallyID, Row>,
+}
+
+struct Row {
+    name: Option<Name>,
+    // Bitset with one bit per entry in `days`
+    present: Vec<u32>,
+    // Seconds of presence by column, only for days with closed check-ins
+    presence: BTreeMap<usize, u64>,
+}
+
+impl Row {
+    fn new(name: Option<Name>, columns: usize) -> Self {
+        Row {
+            name,
+            present: vec![0; columns.div_ceil(32)],
+            presence: BTreeMap::new(),
+        }
+    }
+
+    fn set_present(&mut self, column: usize) {
+        self.present[column / 32] |= 1 << (column % 32);
+    }
+
+    fn is_present(&self, column: usize) -> bool {
+        self.present[column / 32] & (1 << (column % 32)) != 0
+    }
+}
+
+impl AttendanceCsv {
+    pub async fn collect<T: Persistence>(store: &mut IDStore<T>) -> Self {
+        let days = store.list_days().await;
+
+        let mut rows: BTreeMap<TallyID, Row> = BTreeMap::new();
+        for (id, name) in store.mapping.iter() {
+            rows.insert(*id, Row::new(Some(name.clone()), days.len()));
+        }
+
+        for (column, day) in days.iter().enumerate() {
+            let attendance = match store.load_day(*day).await {
+                Ok(Some(attendance)) => attendance,
+                Ok(None) => continue,
+                Err(e) => {
+                    // Skip unreadable days, so one broken file does not prevent the export
+                    warn!("Failed to load day {}: {:?}", day.to_string(), e);
+                    continue;
+                }
+            };
+
+            for attendance in attendance.attendances() {
+                let row = rows
+                    .entry(attendance.id)
+                    .or_insert_with(|| Row::new(None, days.len()));
+                row.set_present(column);
+                if attendance.present_secs > 0 {
+                    row.presence.insert(column, attendance.present_secs);
+                }
+            }
+        }
+
+        AttendanceCsv { days, rows }
+    }
+}
+
+impl Chunks for AttendanceCsv {
+    fn content_type(&self) -> &'static str {
+        "text/csv; charset=utf-8"
+    }
+
+    async fn write_chunks<W: picoserve::io::Write>(
+        self,
+        mut chunk_writer: ChunkWriter<W>,
+    ) -> Result<ChunksWritten, W::Error> {
+        let mut line = String::new();
+
+        line.push_str("ID,Nachname,Vorname");
+        for day in &self.days {
+            let (year, month, day) = day.to_ymd();
+            write!(line, ",{year:04}-{month:02}-{day:02}").ok();
+        }
+        line.push_str("\r\n");
+        chunk_writer.write_chunk(line.as_bytes()).await?;
+
+        for (id, row) in &self.rows {
+            line.clear();
+            write!(line, "{id}").ok();
+
+            let (last, first) = match &row.name {
+                Some(name) => (name.last.as_str(), name.first.as_str()),
+                None => ("", ""),
+            };
+            line.push(',');
+            push_field(&mut line, last);
+            line.push(',');
+            push_field(&mut line, first);
+
+            for column in 0..self.days.len() {
+                match row.presence.get(&column) {
+                    Some(secs) => {
+                        write!(line, ",{}:{:02}", secs / 3600, secs / 60 % 60).ok();
+                    }
+                    None if row.is_present(column) => line.push_str(",x"),
+                    None => line.push(','),
+                }
+            }
+            line.push_str("\r\n");
+
+            chunk_writer.write_chunk(line.as_bytes()).await?;
+        }
+
+        chunk_writer.finalize().await
+    }
+}
+
+/// Append a field and quote it if it contains characters special to CSV
+fn push_field(line: &mut String, field: &str) {
+    if !field.contains([',', '"', '\r', '\n']) {
+        line.push_str(field);
+        return;
+    }
+
+    line.push('"');
+    for c in field.chars() {
+        if c == '"' {
+            line.push('"');
+        }
+        line.push(c);
+    }
+    line.push('"');
+}
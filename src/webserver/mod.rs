@@ -1,4 +1,5 @@
 use alloc::rc::Rc;
+use core::sync::atomic::{AtomicBool, AtomicU32};
 use embassy_executor::Spawner;
 use embassy_net::Stack;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
@@ -7,26 +8,39 @@ use picoserve::{AppRouter, AppWithStateBuilder};
 use static_cell::make_static;
 
 use crate::{
-    TallyChannel, UsedStore,
+    ScanChannel, UsedStore,
+    drivers::rtc::RTCClock,
     webserver::app::{AppProps, AppState},
 };
 
 mod api;
 mod app;
 mod assets;
+mod csv;
 mod sse;
 
+pub use sse::ScanEvent;
+
 pub const WEB_TAKS_SIZE: usize = 3; // Up this number if request start fail with Timeouts.
 
 pub fn start_webserver(
     spawner: &mut Spawner,
     stack: Stack<'static>,
     store: Rc<Mutex<CriticalSectionRawMutex, UsedStore>>,
-    chan: &'static TallyChannel,
+    rtc: Rc<Mutex<CriticalSectionRawMutex, RTCClock>>,
+    chan: &'static ScanChannel,
+    hold_off_ms: &'static AtomicU32,
+    signal_rejected: &'static AtomicBool,
 ) {
     let app = make_static!(AppProps.build_app());
 
-    let state = make_static!(AppState { store, chan });
+    let state = make_static!(AppState {
+        store,
+        rtc,
+        chan,
+        hold_off_ms,
+        signal_rejected,
+    });
 
     let config = make_static!(picoserve::Config::new(picoserve::Timeouts {
         start_read_request: Some(Duration::from_secs(5)),
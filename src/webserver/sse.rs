@@ -2,9 +2,17 @@ use embassy_time::{Duration, Timer};
 use log::warn;
 use picoserve::response;
 
-use crate::TallySubscriber;
+use crate::{ScanSubscriber, store::tally_id::TallyID};
 
-pub struct IDEvents(pub TallySubscriber);
+/// A processed scan, as shown in the web UI
+#[derive(Clone, Copy, Debug)]
+pub struct ScanEvent {
+    pub id: TallyID,
+    /// The ID was already counted today
+    pub already_present: bool,
+}
+
+pub struct IDEvents(pub ScanSubscriber);
 
 impl response::sse::EventSource for IDEvents {
     async fn write_events<W: picoserve::io::Write>(
@@ -17,9 +25,14 @@ impl response::sse::EventSource for IDEvents {
 
             match sel.await {
                 embassy_futures::select::Either::First(msg) => match msg {
-                    embassy_sync::pubsub::WaitResult::Message(id) => {
-                        let id_str: heapless::String<12> = id.into();
-                        writer.write_event("msg", id_str.as_str()).await?
+                    embassy_sync::pubsub::WaitResult::Message(scan) => {
+                        let id_str: heapless::String<10> = scan.id.into();
+                        let event = if scan.already_present {
+                            "duplicate"
+                        } else {
+                            "msg"
+                        };
+                        writer.write_event(event, id_str.as_str()).await?
                     }
                     embassy_sync::pubsub::WaitResult::Lagged(_) => {
                         warn!("SSE subscriber got lagged");
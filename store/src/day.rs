@@ -0,0 +1,160 @@
+use core::fmt::Write;
+
+use embedded_sdmmc::ShortFileName;
+use serde::{Deserialize, Serialize};
+
+use crate::timezone::{LOCAL_TIMEZONE, civil_from_days, days_from_civil};
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Day(u32);
+
+impl Day {
+    const SECONDS_PER_DAY: u64 = 86_400;
+    /// Extension of day files named by their date
+    pub const FILE_EXTENSION: &str = "JSN";
+    /// Extension of day files named by days since 1970 in hex
+    pub const LEGACY_FILE_EXTENSION: &str = "JS";
+
+    pub fn new(daystamp: u32) -> Self {
+        Day(daystamp)
+    }
+
+    /// Days since 1970-01-01 in local time
+    pub fn daystamp(self) -> u32 {
+        self.0
+    }
+
+    /// Local day of a UTC timestamp
+    pub fn new_from_timestamp(time: u64) -> Self {
+        let day = LOCAL_TIMEZONE.to_local(time) / Self::SECONDS_PER_DAY;
+
+        if day > u32::MAX as u64 {
+            // TBH this would only happen if about 11 million years have passed
+            // I sure hope i don't have to work on this project any more then
+            // So we just cap it at this
+            Day(u32::MAX)
+        } else {
+            Day(day as u32)
+        }
+    }
+
+    /// Start of the day in seconds since 1970-01-01 00:00 local time
+    pub fn to_timestamp(self) -> u64 {
+        (self.0 as u64) * Self::SECONDS_PER_DAY
+    }
+
+    /// Calendar date of the day as (year, month, day)
+    pub fn to_ymd(self) -> (i32, u32, u32) {
+        civil_from_days(self.0 as i64)
+    }
+
+    pub fn to_string(self) -> heapless::String<8> {
+        let mut s: heapless::String<8> = heapless::String::new();
+        write!(s, "{:08X}", self.0).unwrap();
+        s
+    }
+
+    pub fn from_hex_str(s: &str) -> Result<Self, &'static str> {
+        if s.len() > 8 {
+            return Err("hex string too long");
+        }
+
+        u32::from_str_radix(s, 16)
+            .map_err(|_| "invalid hex string")
+            .map(Day)
+    }
+
+    /// Date as `YYYYMMDD`
+    pub fn to_date_string(self) -> heapless::String<8> {
+        let (year, month, day) = self.to_ymd();
+        let mut s: heapless::String<8> = heapless::String::new();
+        write!(s, "{:04}{:02}{:02}", year, month, day).unwrap();
+        s
+    }
+
+    pub fn from_date_str(s: &str) -> Result<Self, &'static str> {
+        if s.len() != 8 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err("date string is not YYYYMMDD");
+        }
+
+        let year: i32 = s[..4].parse().map_err(|_| "invalid year")?;
+        let month: u32 = s[4..6].parse().map_err(|_| "invalid month")?;
+        let day: u32 = s[6..].parse().map_err(|_| "invalid day")?;
+
+        let days = days_from_civil(year, month, day);
+        // Out of range months and days don't survive the round trip
+        if civil_from_days(days) != (year, month, day) {
+            return Err("invalid date");
+        }
+
+        u32::try_from(days).map_err(|_| "date before 1970").map(Day)
+    }
+}
+
+impl From<u64> for Day {
+    fn from(value: u64) -> Self {
+        Self::new_from_timestamp(value)
+    }
+}
+
+/// Parses day file names, either `YYYYMMDD.JSN`
+/// or the legacy days since 1970 in hex like `00004E3A.JS`
+impl TryFrom<ShortFileName> for Day {
+    type Error = ();
+
+    fn try_from(value: ShortFileName) -> Result<Self, Self::Error> {
+        let name = core::str::from_utf8(value.base_name()).map_err(|_| ())?;
+
+        match value.extension() {
+            ext if ext == Self::FILE_EXTENSION.as_bytes() => {
+                Self::from_date_str(name).map_err(|_| ())
+            }
+            ext if ext == Self::LEGACY_FILE_EXTENSION.as_bytes() => {
+                Self::from_hex_str(name).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_string_round_trip() {
+        let day = Day::new(20365);
+
+        assert_eq!(day.to_date_string(), "20251004");
+        assert_eq!(Day::from_date_str("20251004"), Ok(day));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        assert!(Day::from_date_str("20250230").is_err());
+        assert!(Day::from_date_str("20251301").is_err());
+        assert!(Day::from_date_str("2025104").is_err());
+        assert!(Day::from_date_str("19691231").is_err());
+    }
+
+    #[test]
+    fn both_filenames_are_parsed() {
+        let day = Day::new(20365);
+        let name = ShortFileName::create_from_str("20251004.JSN").unwrap();
+        let legacy_name = ShortFileName::create_from_str("00004F8D.JS").unwrap();
+
+        assert_eq!(Day::try_from(name), Ok(day));
+        assert_eq!(Day::try_from(legacy_name), Ok(day));
+
+        let temp_name = ShortFileName::create_from_str("20251004.TMP").unwrap();
+        assert!(Day::try_from(temp_name).is_err());
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight() {
+        // 2025-10-04 22:30 UTC is 00:30 CEST on the next day
+        assert_eq!(Day::new_from_timestamp(1_759_617_000), Day::new(20366));
+        // 2025-12-04 22:30 UTC is 23:30 CET on the same day
+        assert_eq!(Day::new_from_timestamp(1_764_887_400), Day::new(20426));
+    }
+}
@@ -0,0 +1,64 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use serde::{Deserialize, Serialize};
+
+use crate::tally_id::TallyID;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Name {
+    pub first: String,
+    pub last: String,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct IDMapping {
+    #[serde(flatten)]
+    id_map: BTreeMap<TallyID, Name>,
+}
+
+impl IDMapping {
+    pub fn new() -> Self {
+        IDMapping {
+            id_map: BTreeMap::new(),
+        }
+    }
+
+    pub fn map(&self, id: &TallyID) -> Option<&Name> {
+        self.id_map.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TallyID, &Name)> {
+        self.id_map.iter()
+    }
+
+    pub fn add_mapping(&mut self, id: TallyID, name: Name) {
+        self.id_map.insert(id, name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serde_round_trip() {
+        let id = TallyID::from_bytes([0, 0, 0, 0, 0xAB]);
+        let mut mapping = IDMapping::new();
+        mapping.add_mapping(
+            id,
+            Name {
+                first: "Max".into(),
+                last: "Mustermann".into(),
+            },
+        );
+
+        let json = serde_json::to_string(&mapping).unwrap();
+        assert_eq!(
+            json,
+            r#"{"00000000AB":{"first":"Max","last":"Mustermann"}}"#
+        );
+
+        let loaded: IDMapping = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.map(&id).unwrap().last, "Mustermann");
+    }
+}
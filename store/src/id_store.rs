@@ -0,0 +1,1323 @@
+use alloc::vec::Vec;
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::{IDMapping, Name};
+use crate::day::Day;
+use crate::persistence::{JournalEntry, Persistence, StorageError};
+use crate::tally_id::TallyID;
+
+/// How repeated scans of an ID on the same day are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanMode {
+    /// Only the first scan of a day counts
+    #[default]
+    Attendance,
+    /// Every scan toggles the ID between checked in and checked out
+    CheckInOut,
+}
+
+/// Outcome of a scan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanResult {
+    /// First scan of the ID on this day
+    Added,
+    /// The ID was already counted on this day
+    AlreadyPresent,
+    CheckedIn,
+    /// Contains the total presence of the day in seconds
+    CheckedOut {
+        present_secs: u64,
+    },
+}
+
+/// Scan of an ID that has no name in the mapping yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct UnknownScan {
+    pub id: TallyID,
+    /// Timestamp of the latest scan
+    pub last_seen: u64,
+}
+
+/// Attendance of a single ID on a day
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(from = "AttendanceRepr")]
+pub struct Attendance {
+    pub id: TallyID,
+    /// Timestamp of the first scan of the day
+    pub first: u64,
+    /// Timestamp of the latest scan of the day
+    pub last: u64,
+    /// Whether the ID is currently checked in. Only used in check-in/out mode.
+    pub checked_in: bool,
+    /// Seconds between check-ins and their check-outs
+    pub present_secs: u64,
+}
+
+impl Attendance {
+    /// Size of the compact encoding of [`Self::to_bytes`]
+    pub const ENCODED_LEN: usize = TallyID::LEN + 4 + 4 + 4 + 1;
+
+    /// Compact encoding for small storage like the FRAM:
+    /// `| id | first | last | present_secs | checked_in |`, all little endian.
+    /// Timestamps are stored as u32, which lasts until 2106.
+    /// The RTC only counts until 2099 anyway.
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let u32_bytes = |value: u64| (value.min(u32::MAX as u64) as u32).to_le_bytes();
+
+        let mut data = [0u8; Self::ENCODED_LEN];
+        let (id, rest) = data.split_at_mut(TallyID::LEN);
+        id.copy_from_slice(&self.id.to_bytes());
+        rest[..4].copy_from_slice(&u32_bytes(self.first));
+        rest[4..8].copy_from_slice(&u32_bytes(self.last));
+        rest[8..12].copy_from_slice(&u32_bytes(self.present_secs));
+        rest[12] = self.checked_in as u8;
+        data
+    }
+
+    pub fn from_bytes(data: &[u8; Self::ENCODED_LEN]) -> Self {
+        let (id, rest) = data.split_at(TallyID::LEN);
+        let u32_at = |offset: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&rest[offset..offset + 4]);
+            u32::from_le_bytes(bytes) as u64
+        };
+
+        let mut id_bytes = [0u8; TallyID::LEN];
+        id_bytes.copy_from_slice(id);
+        Attendance {
+            id: TallyID::from_bytes(id_bytes),
+            first: u32_at(0),
+            last: u32_at(4),
+            present_secs: u32_at(8),
+            checked_in: rest[12] != 0,
+        }
+    }
+
+    /// Total presence in seconds up to `now`.
+    /// An open check-in counts until `now`.
+    pub fn presence(&self, now: u64) -> u64 {
+        if self.checked_in {
+            self.present_secs + now.saturating_sub(self.last)
+        } else {
+            self.present_secs
+        }
+    }
+}
+
+/// Day files written before timestamps were recorded only contain the ID.
+/// Those entries are loaded with both timestamps set to 0.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AttendanceRepr {
+    Legacy(TallyID),
+    Timed {
+        id: TallyID,
+        first: u64,
+        last: u64,
+        #[serde(default)]
+        checked_in: bool,
+        #[serde(default)]
+        present_secs: u64,
+    },
+}
+
+impl From<AttendanceRepr> for Attendance {
+    fn from(value: AttendanceRepr) -> Self {
+        match value {
+            AttendanceRepr::Legacy(id) => Attendance {
+                id,
+                first: 0,
+                last: 0,
+                checked_in: false,
+                present_secs: 0,
+            },
+            AttendanceRepr::Timed {
+                id,
+                first,
+                last,
+                checked_in,
+                present_secs,
+            } => Attendance {
+                id,
+                first,
+                last,
+                checked_in,
+                present_secs,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AttendanceDay {
+    date: Day,
+    ids: Vec<Attendance>,
+}
+
+impl AttendanceDay {
+    pub fn new(date: Day) -> Self {
+        Self {
+            date,
+            ids: Vec::new(),
+        }
+    }
+
+    // Add a scan of an ID at `time` to the day.
+    fn add_id(&mut self, id: TallyID, time: u64, mode: ScanMode) -> ScanResult {
+        let Some(attendance) = self.ids.iter_mut().find(|a| a.id == id) else {
+            self.ids.push(Attendance {
+                id,
+                first: time,
+                last: time,
+                checked_in: mode == ScanMode::CheckInOut,
+                present_secs: 0,
+            });
+
+            return match mode {
+                ScanMode::Attendance => ScanResult::Added,
+                ScanMode::CheckInOut => ScanResult::CheckedIn,
+            };
+        };
+
+        let result = match mode {
+            ScanMode::Attendance => ScanResult::AlreadyPresent,
+            ScanMode::CheckInOut if attendance.checked_in => {
+                attendance.present_secs += time.saturating_sub(attendance.last);
+                attendance.checked_in = false;
+                ScanResult::CheckedOut {
+                    present_secs: attendance.present_secs,
+                }
+            }
+            ScanMode::CheckInOut => {
+                attendance.checked_in = true;
+                ScanResult::CheckedIn
+            }
+        };
+        attendance.last = time;
+
+        result
+    }
+
+    /// Whether a scan of the ID at `time` or later is already recorded
+    fn contains_scan(&self, id: &TallyID, time: u64) -> bool {
+        self.ids.iter().any(|a| a.id == *id && a.last >= time)
+    }
+
+    /// Total presence of an ID in seconds up to `now`
+    pub fn presence(&self, id: &TallyID, now: u64) -> Option<u64> {
+        self.ids
+            .iter()
+            .find(|a| a.id == *id)
+            .map(|a| a.presence(now))
+    }
+
+    /// Merge the scans of another record of the same day into this one.
+    /// The records must not contain each other, e.g. one was started while the
+    /// other could not be loaded. For IDs present in both the closed sessions
+    /// are summed and the state of the later scan wins.
+    pub fn merge(&mut self, other: &AttendanceDay) {
+        for theirs in &other.ids {
+            let Some(ours) = self.ids.iter_mut().find(|a| a.id == theirs.id) else {
+                self.ids.push(*theirs);
+                continue;
+            };
+
+            ours.first = ours.first.min(theirs.first);
+            ours.present_secs += theirs.present_secs;
+            if theirs.last > ours.last {
+                ours.last = theirs.last;
+                ours.checked_in = theirs.checked_in;
+            }
+        }
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &TallyID> {
+        self.ids.iter().map(|a| &a.id)
+    }
+
+    pub fn attendances(&self) -> &[Attendance] {
+        &self.ids
+    }
+
+    /// Compact encoding of all attendances, see [`Attendance::to_bytes`]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.ids.iter().flat_map(|a| a.to_bytes()).collect()
+    }
+
+    /// Decode the attendances of `date` from [`Self::to_bytes`].
+    /// Returns None if the data is not made of whole records.
+    pub fn from_bytes(date: Day, data: &[u8]) -> Option<Self> {
+        let records = data.chunks_exact(Attendance::ENCODED_LEN);
+        if !records.remainder().is_empty() {
+            return None;
+        }
+
+        let ids = records
+            .map(|record| Attendance::from_bytes(record.try_into().unwrap()))
+            .collect();
+        Some(Self { date, ids })
+    }
+}
+
+/// Maximum number of past days kept in memory while they can not be written to storage
+const MAX_PENDING_DAYS: usize = 7;
+
+/// A past day that could not be written yet
+#[derive(Clone)]
+struct PendingDay {
+    day: AttendanceDay,
+    /// Whether the day already contains the stored version, see `IDStore::day_synced`
+    synced: bool,
+}
+
+/// Maximum number of unknown IDs waiting for a name, the oldest is dropped first
+const MAX_UNKNOWN_IDS: usize = 16;
+
+#[derive(Clone)]
+pub struct IDStore<T: Persistence> {
+    pub current_day: AttendanceDay,
+    pub mapping: IDMapping,
+    mode: ScanMode,
+    persistence_layer: T,
+    /// Past days that could not be written yet
+    pending_days: heapless::Vec<PendingDay, MAX_PENDING_DAYS>,
+    /// Unknown IDs waiting for a name, latest scan last
+    unknown_ids: heapless::Vec<UnknownScan, MAX_UNKNOWN_IDS>,
+    // Whether the data in memory was merged with the data on the storage.
+    // This is false if loading failed, e.g. because the SD card was missing.
+    day_synced: bool,
+    mapping_synced: bool,
+    mode_synced: bool,
+    // Whether the latest changes were written to the storage
+    day_saved: bool,
+    mapping_saved: bool,
+    mode_saved: bool,
+    // Whether the times of repeated scans were written, see `Self::add_id`
+    scan_times_saved: bool,
+}
+
+impl<T: Persistence> IDStore<T> {
+    /// Load the mapping and the attendance of `current_date` from storage,
+    /// so scans from before a reboot are not lost.
+    /// If the storage fails the store starts empty and merges with the
+    /// storage on the next successful write.
+    pub async fn new_from_storage(mut persistence_layer: T, current_date: Day) -> Self {
+        let (mapping, mapping_synced) =
+            match Self::load_writable_mapping(&mut persistence_layer).await {
+                Ok(Some(map)) => (map, true),
+                Ok(None) => (IDMapping::new(), true),
+                Err(e) => {
+                    error!("Failed to load mapping: {:?}", e);
+                    (IDMapping::new(), false)
+                }
+            };
+
+        let (mode, mode_synced) = match persistence_layer.load_mode().await {
+            Ok(mode) => (mode.unwrap_or_default(), true),
+            Err(e) => {
+                error!("Failed to load mode: {:?}", e);
+                (ScanMode::default(), false)
+            }
+        };
+
+        let (day, day_synced) = Self::load_day_or_new(&mut persistence_layer, current_date).await;
+
+        let mut store = Self {
+            current_day: day,
+            mapping,
+            mode,
+            persistence_layer,
+            pending_days: heapless::Vec::new(),
+            unknown_ids: heapless::Vec::new(),
+            day_synced,
+            mapping_synced,
+            mode_synced,
+            day_saved: true,
+            mapping_saved: true,
+            mode_saved: true,
+            scan_times_saved: true,
+        };
+
+        if store.replay_journal(current_date).await {
+            if let Err(e) = store.write_unsaved().await {
+                error!("Failed to write replayed scans: {:?}", e);
+            }
+        }
+        store
+    }
+
+    /// Load the current day again after the storage medium changed.
+    /// Scans that were not written yet are taken from the journal,
+    /// the mapping and the mode are merged with the stored ones on the next write.
+    async fn reload(&mut self) {
+        let date = self.current_day.date;
+        let (day, day_synced) = Self::load_day_or_new(&mut self.persistence_layer, date).await;
+        self.current_day = day;
+        self.day_synced = day_synced;
+        self.day_saved = true;
+        self.scan_times_saved = true;
+        self.pending_days.clear();
+        self.mapping_synced = false;
+        self.mode_synced = false;
+
+        self.replay_journal(date).await;
+    }
+
+    /// Apply scans from the journal that did not make it to the storage,
+    /// e.g. because power was lost while writing.
+    /// Returns true if any scans were applied.
+    async fn replay_journal(&mut self, current_date: Day) -> bool {
+        let entries = match self.persistence_layer.journal_entries().await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read journal: {:?}", e);
+                return false;
+            }
+        };
+
+        if entries.is_empty() {
+            return false;
+        }
+        info!("Replaying {} scans from the journal", entries.len());
+
+        for entry in entries {
+            self.switch_day(entry.timestamp.into()).await;
+
+            // The scan might have been written before the journal was cleared
+            if self.current_day.contains_scan(&entry.id, entry.timestamp) {
+                continue;
+            }
+
+            self.current_day
+                .add_id(entry.id, entry.timestamp, entry.mode);
+            self.day_saved = false;
+        }
+
+        self.switch_day(current_date).await;
+        true
+    }
+
+    /// Make `date` the current day.
+    /// The previous day is kept in memory if it could not be written yet.
+    async fn switch_day(&mut self, date: Day) {
+        if self.current_day.date == date {
+            return;
+        }
+
+        if self.day_saved && !self.scan_times_saved {
+            // A failed write is handled like any unsaved day below
+            if let Err(e) = self.persist_day().await {
+                warn!("Failed to write the times of repeated scans: {:?}", e);
+            }
+        }
+
+        let (new_day, synced) = Self::load_day_or_new(&mut self.persistence_layer, date).await;
+        let old_day = core::mem::replace(&mut self.current_day, new_day);
+
+        if !self.day_saved {
+            self.queue_pending(PendingDay {
+                day: old_day,
+                synced: self.day_synced,
+            });
+        }
+        self.day_synced = synced;
+        self.day_saved = true;
+    }
+
+    /// Load a stored day for writing to it.
+    /// An unreadable day is moved out of the way and started anew,
+    /// otherwise no scan of that day could be written anymore.
+    async fn load_writable_day(
+        persistence_layer: &mut T,
+        date: Day,
+    ) -> Result<Option<AttendanceDay>, StorageError> {
+        match persistence_layer.load_day(date).await {
+            Err(StorageError::Serialization) => {
+                error!(
+                    "Day {} is corrupt, moving it aside and starting it anew",
+                    date.to_string()
+                );
+                persistence_layer.discard_day(date).await?;
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Load the stored mapping for writing to it.
+    /// An unreadable mapping is moved out of the way and replaced by the one in memory,
+    /// otherwise no name could be saved anymore.
+    async fn load_writable_mapping(
+        persistence_layer: &mut T,
+    ) -> Result<Option<IDMapping>, StorageError> {
+        match persistence_layer.load_mapping().await {
+            Err(StorageError::Serialization) => {
+                error!("Mapping is corrupt, moving it aside and starting it anew");
+                persistence_layer.discard_mapping().await?;
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the loaded day and whether loading succeeded
+    async fn load_day_or_new(persistence_layer: &mut T, date: Day) -> (AttendanceDay, bool) {
+        match Self::load_writable_day(persistence_layer, date).await {
+            Ok(Some(day)) => (day, true),
+            Ok(None) => (AttendanceDay::new(date), true),
+            Err(e) => {
+                error!("Failed to load day {}: {:?}", date.to_string(), e);
+                (AttendanceDay::new(date), false)
+            }
+        }
+    }
+
+    /// Merge a day with the stored version and write it.
+    /// `day` only becomes the merged version once it is written,
+    /// so a failed write does not merge the stored version twice.
+    async fn merge_and_save(
+        persistence_layer: &mut T,
+        day: &mut AttendanceDay,
+    ) -> Result<(), StorageError> {
+        let Some(stored) = Self::load_writable_day(persistence_layer, day.date).await? else {
+            return persistence_layer.save_day(day.date, day).await;
+        };
+
+        let mut merged = day.clone();
+        merged.merge(&stored);
+        persistence_layer.save_day(merged.date, &merged).await?;
+        *day = merged;
+        Ok(())
+    }
+
+    async fn persist_day(&mut self) -> Result<(), StorageError> {
+        let result = if self.day_synced {
+            self.persistence_layer
+                .save_day(self.current_day.date, &self.current_day)
+                .await
+        } else {
+            Self::merge_and_save(&mut self.persistence_layer, &mut self.current_day).await
+        };
+
+        self.day_synced |= result.is_ok();
+        self.day_saved = result.is_ok();
+        self.scan_times_saved |= result.is_ok();
+        result
+    }
+
+    async fn persist_mapping(&mut self) -> Result<(), StorageError> {
+        if !self.mapping_synced {
+            if let Some(mut stored) =
+                Self::load_writable_mapping(&mut self.persistence_layer).await?
+            {
+                // Mappings made while the storage was unavailable are newer
+                for (id, name) in self.mapping.iter() {
+                    stored.add_mapping(*id, name.clone());
+                }
+                self.mapping = stored;
+            }
+            self.mapping_synced = true;
+        }
+
+        let result = self.persistence_layer.save_mapping(&self.mapping).await;
+        self.mapping_saved = result.is_ok();
+        result
+    }
+
+    /// Write a mode set while the storage was unavailable,
+    /// otherwise take over the stored mode
+    async fn sync_mode(&mut self) -> Result<(), StorageError> {
+        if self.mode_saved {
+            match self.persistence_layer.load_mode().await {
+                Ok(Some(mode)) => self.mode = mode,
+                Ok(None) => {}
+                // Nothing worth keeping, it is replaced below
+                Err(StorageError::Serialization) => {
+                    error!("Stored mode is corrupt, replacing it");
+                    self.mode_saved = false;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !self.mode_saved {
+            self.persistence_layer.save_mode(self.mode).await?;
+            self.mode_saved = true;
+        }
+
+        self.mode_synced = true;
+        Ok(())
+    }
+
+    /// Keep a past day in memory until it can be written.
+    /// If too many days are pending the oldest one is lost.
+    fn queue_pending(&mut self, day: PendingDay) {
+        if self.pending_days.is_full() {
+            let dropped = self.pending_days.remove(0);
+            error!(
+                "Too many unsaved days, dropping day {}",
+                dropped.day.date.to_string()
+            );
+        }
+        // There is always space left after the removal above
+        let _ = self.pending_days.push(day);
+    }
+
+    /// Write everything that could not be persisted before,
+    /// e.g. after the SD card was inserted again.
+    pub async fn flush(&mut self) -> Result<(), StorageError> {
+        if self.persistence_layer.reconnect().await? {
+            info!("Storage medium changed, loading the data again");
+            self.reload().await;
+        }
+
+        self.write_unsaved().await
+    }
+
+    async fn write_unsaved(&mut self) -> Result<(), StorageError> {
+        // The days go first, so a broken mapping or mode can't hold back the scans
+        self.write_pending_days().await?;
+        if !self.day_saved || !self.scan_times_saved {
+            self.persist_day().await?;
+        }
+        self.persistence_layer.journal_clear().await?;
+
+        let mapping_result = if !self.mapping_synced || !self.mapping_saved {
+            self.persist_mapping().await
+        } else {
+            Ok(())
+        };
+        if let Err(e) = mapping_result {
+            error!("Failed to write mapping: {:?}", e);
+        }
+
+        let mode_result = if !self.mode_synced || !self.mode_saved {
+            self.sync_mode().await
+        } else {
+            Ok(())
+        };
+        if let Err(e) = mode_result {
+            error!("Failed to write mode: {:?}", e);
+        }
+
+        mapping_result.and(mode_result)
+    }
+
+    /// Write the past days that could not be written before, oldest first
+    async fn write_pending_days(&mut self) -> Result<(), StorageError> {
+        while let Some(pending) = self.pending_days.first_mut() {
+            if pending.synced {
+                let day = &pending.day;
+                self.persistence_layer.save_day(day.date, day).await?;
+            } else {
+                Self::merge_and_save(&mut self.persistence_layer, &mut pending.day).await?;
+            }
+            self.pending_days.remove(0);
+        }
+
+        Ok(())
+    }
+
+    /// Add or replace the name of an ID and write the mapping to storage.
+    /// Returns Err if the mapping could not be persisted.
+    /// The mapping is kept in memory and written on the next [`Self::flush`] then.
+    pub async fn add_mapping(&mut self, id: TallyID, name: Name) -> Result<(), StorageError> {
+        self.mapping.add_mapping(id, name);
+        self.unknown_ids.retain(|scan| scan.id != id);
+        self.persist_mapping().await
+    }
+
+    /// How repeated scans of an ID are counted
+    pub fn mode(&self) -> ScanMode {
+        self.mode
+    }
+
+    /// Change how scans are counted and write the mode to storage.
+    /// Returns Err if the mode could not be persisted.
+    /// The mode is used anyway and written on the next [`Self::flush`] then.
+    pub async fn set_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        self.mode = mode;
+
+        let result = self.persistence_layer.save_mode(mode).await;
+        self.mode_saved = result.is_ok();
+        self.mode_synced |= result.is_ok();
+        result
+    }
+
+    /// Whether the ID has a name in the mapping
+    pub fn is_known(&self, id: &TallyID) -> bool {
+        self.mapping.map(id).is_some()
+    }
+
+    /// Scanned IDs that have no name yet, latest scan last
+    pub fn unknown_ids(&self) -> &[UnknownScan] {
+        &self.unknown_ids
+    }
+
+    fn remember_unknown(&mut self, id: TallyID, timestamp: u64) {
+        self.unknown_ids.retain(|scan| scan.id != id);
+        if self.unknown_ids.is_full() {
+            self.unknown_ids.remove(0);
+        }
+        // There is always space left after the removal above
+        let _ = self.unknown_ids.push(UnknownScan {
+            id,
+            last_seen: timestamp,
+        });
+    }
+
+    /// List all days that have attendance stored or pending, sorted by date.
+    /// If the storage can not be read only the days in memory are listed.
+    pub async fn list_days(&mut self) -> Vec<Day> {
+        let mut days = match self.persistence_layer.list_days().await {
+            Ok(days) => days,
+            Err(e) => {
+                error!("Failed to list stored days: {:?}", e);
+                Vec::new()
+            }
+        };
+        days.extend(self.pending_days.iter().map(|p| p.day.date));
+        if !self.current_day.ids.is_empty() {
+            days.push(self.current_day.date);
+        }
+
+        days.sort_unstable();
+        days.dedup();
+        days
+    }
+
+    /// Load the attendance of a day.
+    /// The current day and pending days are served from memory.
+    pub async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        if self.current_day.date == day {
+            return Ok(Some(self.current_day.clone()));
+        }
+
+        let pending = self.pending_days.iter().find(|p| p.day.date == day);
+        if let Some(pending) = pending.filter(|p| p.synced) {
+            return Ok(Some(pending.day.clone()));
+        }
+
+        let stored = self.persistence_layer.load_day(day).await?;
+        match pending {
+            Some(pending) => {
+                let mut merged = stored.unwrap_or(AttendanceDay::new(day));
+                merged.merge(&pending.day);
+                Ok(Some(merged))
+            }
+            None => Ok(stored),
+        }
+    }
+
+    /// Add a scan of an id at `timestamp` to the day the timestamp falls on.
+    /// The time of the latest scan is updated in any case.
+    /// IDs without a name are counted as well and listed in [`Self::unknown_ids`].
+    ///
+    /// Check-ins are not carried over to the next day, so a check-out after
+    /// midnight starts a new check-in on the new day.
+    ///
+    /// A repeated scan in attendance mode only changes the time of the latest scan.
+    /// To spare the storage it is only journaled and written with the next change of the day.
+    /// If it can not be journaled the day is written right away.
+    ///
+    /// Returns Err if the day could not be persisted.
+    /// The scan is kept in memory and written on the next [`Self::flush`] then.
+    pub async fn add_id(
+        &mut self,
+        id: TallyID,
+        timestamp: u64,
+    ) -> Result<ScanResult, StorageError> {
+        self.switch_day(timestamp.into()).await;
+
+        if !self.is_known(&id) {
+            self.remember_unknown(id, timestamp);
+        }
+
+        let result = self.current_day.add_id(id, timestamp, self.mode);
+
+        // Record the scan before writing, so it survives a power loss while writing the day
+        let entry = JournalEntry {
+            id,
+            timestamp,
+            mode: self.mode,
+        };
+        let journaled = match self.persistence_layer.journal_append(entry).await {
+            Ok(()) => true,
+            Err(e) => {
+                error!(
+                    "Failed to journal scan of {}, it is lost on a power loss until it is written: {:?}",
+                    id, e
+                );
+                false
+            }
+        };
+
+        if result == ScanResult::AlreadyPresent && self.day_saved && journaled {
+            self.scan_times_saved = false;
+            return Ok(result);
+        }
+
+        self.persist_day().await?;
+
+        // The storage works again, even if the insertion of a card was missed
+        if let Err(e) = self.write_pending_days().await {
+            warn!("Failed to write pending days: {:?}", e);
+        }
+
+        // Everything is committed, unless older days are still waiting to be written
+        let journal_result = if self.pending_days.is_empty() {
+            self.persistence_layer.journal_clear().await
+        } else {
+            self.trim_journal().await
+        };
+        if let Err(e) = journal_result {
+            warn!("Failed to clear journal: {:?}", e);
+        }
+
+        Ok(result)
+    }
+
+    /// Drop the journaled scans of days that are written,
+    /// so the journal keeps space for scans while older days are pending
+    async fn trim_journal(&mut self) -> Result<(), StorageError> {
+        let pending: heapless::Vec<Day, MAX_PENDING_DAYS> =
+            self.pending_days.iter().map(|p| p.day.date).collect();
+
+        self.persistence_layer
+            .journal_retain(|entry| pending.contains(&Day::from(entry.timestamp)))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::memory::MemoryPersistence;
+
+    /// 2025-10-04 12:00 UTC, 14:00 local time
+    const NOON: u64 = 1_759_579_200;
+    const HOUR: u64 = 3_600;
+    const DAY: u64 = 86_400;
+
+    fn id(n: u8) -> TallyID {
+        TallyID::from_bytes([0, 0, 0, 0, n])
+    }
+
+    fn name(last: &str) -> Name {
+        Name {
+            first: "Max".into(),
+            last: last.into(),
+        }
+    }
+
+    fn new_store(persistence: MemoryPersistence) -> IDStore<MemoryPersistence> {
+        block_on(IDStore::new_from_storage(persistence, NOON.into()))
+    }
+
+    #[test]
+    fn repeated_scans_are_counted_once() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        assert_eq!(block_on(store.add_id(id(1), NOON)), Ok(ScanResult::Added));
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON + HOUR)),
+            Ok(ScanResult::AlreadyPresent)
+        );
+        assert_eq!(
+            block_on(store.add_id(id(2), NOON + HOUR)),
+            Ok(ScanResult::Added)
+        );
+
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(day.ids().count(), 2);
+        let attendance = day.attendances()[0];
+        assert_eq!((attendance.first, attendance.last), (NOON, NOON + HOUR));
+    }
+
+    #[test]
+    fn scan_on_the_next_day_starts_a_new_day() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON + DAY)),
+            Ok(ScanResult::Added)
+        );
+
+        assert_eq!(store.current_day.date, Day::from(NOON + DAY));
+        assert_eq!(
+            block_on(store.list_days()),
+            [Day::from(NOON), Day::from(NOON + DAY)]
+        );
+    }
+
+    #[test]
+    fn days_in_memory_are_listed_while_storage_is_unavailable() {
+        let mut store = new_store(MemoryPersistence::new());
+        block_on(store.add_id(id(1), NOON)).unwrap();
+
+        store.persistence_layer.unavailable = true;
+        assert!(block_on(store.add_id(id(1), NOON + DAY)).is_err());
+        assert!(block_on(store.add_id(id(1), NOON + 2 * DAY)).is_err());
+
+        assert_eq!(
+            block_on(store.list_days()),
+            [Day::from(NOON + DAY), Day::from(NOON + 2 * DAY)]
+        );
+    }
+
+    #[test]
+    fn check_in_out_sums_presence() {
+        let mut store = new_store(MemoryPersistence::new());
+        store.mode = ScanMode::CheckInOut;
+
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON)),
+            Ok(ScanResult::CheckedIn)
+        );
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON + HOUR)),
+            Ok(ScanResult::CheckedOut { present_secs: HOUR })
+        );
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON + 2 * HOUR)),
+            Ok(ScanResult::CheckedIn)
+        );
+
+        assert_eq!(
+            store.current_day.presence(&id(1), NOON + 3 * HOUR),
+            Some(2 * HOUR)
+        );
+    }
+
+    #[test]
+    fn days_are_kept_while_storage_is_unavailable() {
+        let mut store = new_store(MemoryPersistence::new());
+        store.persistence_layer.unavailable = true;
+
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON)),
+            Err(StorageError::Device)
+        );
+        assert_eq!(
+            block_on(store.add_id(id(2), NOON + DAY)),
+            Err(StorageError::Device)
+        );
+
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+
+        let days = &store.persistence_layer.days;
+        assert_eq!(days[&Day::from(NOON)].ids().collect::<Vec<_>>(), [&id(1)]);
+        assert_eq!(
+            days[&Day::from(NOON + DAY)].ids().collect::<Vec<_>>(),
+            [&id(2)]
+        );
+    }
+
+    #[test]
+    fn scans_are_merged_with_storage_after_failed_load() {
+        let mut earlier = new_store(MemoryPersistence::new());
+        block_on(earlier.add_id(id(1), NOON)).unwrap();
+
+        let mut persistence = earlier.persistence_layer;
+        persistence.unavailable = true;
+        let mut store = new_store(persistence);
+
+        assert!(block_on(store.add_id(id(2), NOON + HOUR)).is_err());
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert!(day.ids().any(|i| *i == id(1)));
+        assert!(day.ids().any(|i| *i == id(2)));
+    }
+
+    #[test]
+    fn merge_sums_presence_of_both_records() {
+        let mut stored = AttendanceDay::new(NOON.into());
+        stored.add_id(id(1), NOON, ScanMode::CheckInOut);
+        stored.add_id(id(1), NOON + HOUR, ScanMode::CheckInOut);
+
+        // Started without the stored record
+        let mut offline = AttendanceDay::new(NOON.into());
+        offline.add_id(id(1), NOON + 2 * HOUR, ScanMode::CheckInOut);
+        offline.add_id(id(1), NOON + 4 * HOUR, ScanMode::CheckInOut);
+        offline.add_id(id(1), NOON + 5 * HOUR, ScanMode::CheckInOut);
+
+        offline.merge(&stored);
+
+        let attendance = offline.attendances()[0];
+        assert_eq!(attendance.present_secs, 3 * HOUR);
+        assert_eq!((attendance.first, attendance.last), (NOON, NOON + 5 * HOUR));
+        assert!(attendance.checked_in);
+    }
+
+    #[test]
+    fn presence_is_kept_after_failed_load() {
+        let mut earlier = new_store(MemoryPersistence::new());
+        earlier.mode = ScanMode::CheckInOut;
+        block_on(earlier.add_id(id(1), NOON)).unwrap();
+        block_on(earlier.add_id(id(1), NOON + HOUR)).unwrap();
+
+        let mut persistence = earlier.persistence_layer;
+        persistence.unavailable = true;
+        let mut store = new_store(persistence);
+        store.mode = ScanMode::CheckInOut;
+
+        assert!(block_on(store.add_id(id(1), NOON + 2 * HOUR)).is_err());
+        assert!(block_on(store.add_id(id(1), NOON + 3 * HOUR)).is_err());
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(day.presence(&id(1), NOON + 4 * HOUR), Some(2 * HOUR));
+    }
+
+    #[test]
+    fn pending_day_presence_is_not_counted_twice() {
+        let mut store = new_store(MemoryPersistence::new());
+        store.mode = ScanMode::CheckInOut;
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        block_on(store.add_id(id(1), NOON + HOUR)).unwrap();
+
+        store.persistence_layer.unavailable = true;
+        assert!(block_on(store.add_id(id(1), NOON + 2 * HOUR)).is_err());
+        assert!(block_on(store.add_id(id(1), NOON + 3 * HOUR)).is_err());
+        assert!(block_on(store.add_id(id(2), NOON + DAY)).is_err());
+
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(day.presence(&id(1), NOON + DAY), Some(2 * HOUR));
+    }
+
+    #[test]
+    fn corrupt_day_is_started_anew() {
+        let mut persistence = MemoryPersistence::new();
+        persistence.corrupt_days.insert(Day::from(NOON));
+        let mut store = new_store(persistence);
+
+        assert_eq!(block_on(store.add_id(id(1), NOON)), Ok(ScanResult::Added));
+
+        assert!(store.persistence_layer.corrupt_days.is_empty());
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(day.ids().collect::<Vec<_>>(), [&id(1)]);
+    }
+
+    #[test]
+    fn day_corrupted_while_unavailable_is_started_anew() {
+        let mut persistence = MemoryPersistence::new();
+        persistence.unavailable = true;
+        let mut store = new_store(persistence);
+
+        assert!(block_on(store.add_id(id(1), NOON)).is_err());
+        store.persistence_layer.unavailable = false;
+        store.persistence_layer.corrupt_days.insert(Day::from(NOON));
+        block_on(store.flush()).unwrap();
+
+        assert!(store.persistence_layer.corrupt_days.is_empty());
+        let day = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(day.ids().collect::<Vec<_>>(), [&id(1)]);
+    }
+
+    #[test]
+    fn mode_is_persisted() {
+        let mut store = new_store(MemoryPersistence::new());
+        block_on(store.set_mode(ScanMode::CheckInOut)).unwrap();
+
+        let store = new_store(store.persistence_layer);
+        assert_eq!(store.mode(), ScanMode::CheckInOut);
+    }
+
+    #[test]
+    fn mode_set_while_unavailable_is_written_on_flush() {
+        let mut store = new_store(MemoryPersistence::new());
+        store.persistence_layer.unavailable = true;
+        assert!(block_on(store.set_mode(ScanMode::CheckInOut)).is_err());
+        assert_eq!(store.mode(), ScanMode::CheckInOut);
+
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+        assert_eq!(store.persistence_layer.mode, Some(ScanMode::CheckInOut));
+    }
+
+    #[test]
+    fn repeated_scan_is_written_with_the_next_change() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        assert_eq!(
+            block_on(store.add_id(id(1), NOON + HOUR)),
+            Ok(ScanResult::AlreadyPresent)
+        );
+        let stored = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(stored.attendances()[0].last, NOON);
+        assert_eq!(store.persistence_layer.journal.len(), 1);
+
+        block_on(store.add_id(id(2), NOON + DAY)).unwrap();
+        let stored = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(stored.attendances()[0].last, NOON + HOUR);
+    }
+
+    #[test]
+    fn repeated_scan_survives_a_power_loss() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        block_on(store.add_id(id(1), NOON + HOUR)).unwrap();
+
+        // Power loss before the day was written again
+        let store = new_store(store.persistence_layer);
+
+        assert_eq!(store.current_day.attendances()[0].last, NOON + HOUR);
+        let stored = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(stored.attendances()[0].last, NOON + HOUR);
+        assert!(store.persistence_layer.journal.is_empty());
+    }
+
+    #[test]
+    fn data_is_loaded_again_when_the_medium_changed() {
+        let mut store = new_store(MemoryPersistence::new());
+        block_on(store.add_mapping(id(1), name("Mustermann"))).unwrap();
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        block_on(store.add_id(id(1), NOON + HOUR)).unwrap();
+
+        // The new medium got a copy of the old one and has data of its own
+        let mut medium = store.persistence_layer.clone();
+        let mut day = medium.days[&Day::from(NOON)].clone();
+        day.add_id(id(2), NOON, ScanMode::Attendance);
+        medium.days.insert(day.date, day);
+        let mut mapping = IDMapping::new();
+        mapping.add_mapping(id(2), name("Musterfrau"));
+        medium.mapping = Some(mapping);
+        medium.medium_changed = true;
+        store.persistence_layer = medium;
+
+        block_on(store.flush()).unwrap();
+
+        let attendances = store.current_day.attendances();
+        assert_eq!(attendances.len(), 2);
+        assert_eq!(attendances[0].last, NOON + HOUR);
+        assert!(store.mapping.map(&id(1)).is_some());
+        assert!(store.mapping.map(&id(2)).is_some());
+        let stored = &store.persistence_layer.days[&Day::from(NOON)];
+        assert_eq!(stored.attendances().len(), 2);
+        assert_eq!(stored.attendances()[0].last, NOON + HOUR);
+        assert!(store.persistence_layer.journal.is_empty());
+    }
+
+    #[test]
+    fn mapping_is_persisted() {
+        let mut store = new_store(MemoryPersistence::new());
+        block_on(store.add_mapping(id(1), name("Mustermann"))).unwrap();
+
+        let store = new_store(store.persistence_layer);
+        assert_eq!(store.mapping.map(&id(1)).unwrap().last, "Mustermann");
+    }
+
+    #[test]
+    fn unknown_ids_wait_for_a_name() {
+        let mut store = new_store(MemoryPersistence::new());
+        block_on(store.add_mapping(id(1), name("Mustermann"))).unwrap();
+
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        block_on(store.add_id(id(2), NOON)).unwrap();
+        block_on(store.add_id(id(3), NOON + 1)).unwrap();
+        block_on(store.add_id(id(2), NOON + 2)).unwrap();
+
+        // Unknown IDs are still counted
+        assert_eq!(store.current_day.attendances().len(), 3);
+        assert!(!store.is_known(&id(2)));
+        assert_eq!(
+            store.unknown_ids(),
+            [
+                UnknownScan {
+                    id: id(3),
+                    last_seen: NOON + 1
+                },
+                UnknownScan {
+                    id: id(2),
+                    last_seen: NOON + 2
+                },
+            ]
+        );
+
+        block_on(store.add_mapping(id(2), name("Musterfrau"))).unwrap();
+        assert!(store.is_known(&id(2)));
+        assert_eq!(store.unknown_ids().len(), 1);
+        assert_eq!(store.unknown_ids()[0].id, id(3));
+    }
+
+    #[test]
+    fn oldest_unknown_id_is_dropped() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        for n in 0..=MAX_UNKNOWN_IDS as u8 {
+            block_on(store.add_id(id(n), NOON)).unwrap();
+        }
+
+        assert_eq!(store.unknown_ids().len(), MAX_UNKNOWN_IDS);
+        assert_eq!(store.unknown_ids()[0].id, id(1));
+    }
+
+    #[test]
+    fn offline_mapping_is_merged_with_storage() {
+        let mut earlier = new_store(MemoryPersistence::new());
+        block_on(earlier.add_mapping(id(1), name("Mustermann"))).unwrap();
+
+        let mut persistence = earlier.persistence_layer;
+        persistence.unavailable = true;
+        let mut store = new_store(persistence);
+
+        assert!(block_on(store.add_mapping(id(2), name("Musterfrau"))).is_err());
+        store.persistence_layer.unavailable = false;
+        block_on(store.flush()).unwrap();
+
+        let stored = store.persistence_layer.mapping.as_ref().unwrap();
+        assert!(stored.map(&id(1)).is_some());
+        assert!(stored.map(&id(2)).is_some());
+    }
+
+    #[test]
+    fn journal_is_cleared_after_write() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        block_on(store.add_id(id(1), NOON)).unwrap();
+        assert!(store.persistence_layer.journal.is_empty());
+    }
+
+    #[test]
+    fn journaled_scans_are_replayed() {
+        let mut persistence = MemoryPersistence::new();
+        persistence.journal.push(JournalEntry {
+            id: id(1),
+            timestamp: NOON,
+            mode: ScanMode::Attendance,
+        });
+
+        let store = new_store(persistence);
+
+        assert_eq!(store.current_day.ids().collect::<Vec<_>>(), [&id(1)]);
+        assert!(store.persistence_layer.days.contains_key(&Day::from(NOON)));
+        assert!(store.persistence_layer.journal.is_empty());
+    }
+
+    #[test]
+    fn replay_skips_written_scans() {
+        let mut earlier = new_store(MemoryPersistence::new());
+        earlier.mode = ScanMode::CheckInOut;
+        block_on(earlier.add_id(id(1), NOON)).unwrap();
+
+        // Power was lost before the journal was cleared
+        let mut persistence = earlier.persistence_layer;
+        persistence.journal.push(JournalEntry {
+            id: id(1),
+            timestamp: NOON,
+            mode: ScanMode::CheckInOut,
+        });
+
+        let store = new_store(persistence);
+        assert!(store.current_day.attendances()[0].checked_in);
+    }
+
+    #[test]
+    fn days_are_flushed_before_the_mapping() {
+        let mut persistence = MemoryPersistence::new();
+        persistence.unavailable = true;
+        let mut store = new_store(persistence);
+
+        assert!(block_on(store.add_id(id(1), NOON)).is_err());
+        store.persistence_layer.unavailable = false;
+        store.persistence_layer.corrupt_mapping = true;
+
+        block_on(store.flush()).unwrap();
+        assert!(!store.persistence_layer.corrupt_mapping);
+        let days = &store.persistence_layer.days;
+        assert_eq!(days[&Day::from(NOON)].ids().collect::<Vec<_>>(), [&id(1)]);
+    }
+
+    #[test]
+    fn corrupt_mapping_is_replaced() {
+        let mut persistence = MemoryPersistence::new();
+        persistence.corrupt_mapping = true;
+        let mut store = new_store(persistence);
+        assert!(!store.persistence_layer.corrupt_mapping);
+
+        block_on(store.add_mapping(id(1), name("Mustermann"))).unwrap();
+        let stored = store.persistence_layer.mapping.as_ref().unwrap();
+        assert!(stored.map(&id(1)).is_some());
+    }
+
+    #[test]
+    fn pending_days_are_written_with_the_next_scan() {
+        let mut store = new_store(MemoryPersistence::new());
+
+        store.persistence_layer.unavailable = true;
+        assert!(block_on(store.add_id(id(1), NOON)).is_err());
+        assert!(block_on(store.add_id(id(2), NOON + DAY)).is_err());
+
+        // Without a flush, e.g. because the insertion of the card was missed
+        store.persistence_layer.unavailable = false;
+        block_on(store.add_id(id(3), NOON + DAY)).unwrap();
+
+        let days = &store.persistence_layer.days;
+        assert_eq!(days[&Day::from(NOON)].ids().collect::<Vec<_>>(), [&id(1)]);
+        assert!(store.persistence_layer.journal.is_empty());
+    }
+
+    #[test]
+    fn day_binary_round_trip() {
+        let mut day = AttendanceDay::new(NOON.into());
+        day.add_id(id(1), NOON, ScanMode::CheckInOut);
+        day.add_id(id(1), NOON + HOUR, ScanMode::CheckInOut);
+        day.add_id(id(2), NOON + HOUR, ScanMode::CheckInOut);
+
+        let data = day.to_bytes();
+        assert_eq!(data.len(), 2 * Attendance::ENCODED_LEN);
+
+        let loaded = AttendanceDay::from_bytes(day.date, &data).unwrap();
+        let attendances = loaded.attendances();
+        assert_eq!(attendances.len(), 2);
+        assert_eq!(attendances[0].id, id(1));
+        assert_eq!(
+            (attendances[0].first, attendances[0].last),
+            (NOON, NOON + HOUR)
+        );
+        assert_eq!(attendances[0].present_secs, HOUR);
+        assert!(!attendances[0].checked_in);
+        assert!(attendances[1].checked_in);
+
+        assert!(AttendanceDay::from_bytes(day.date, &data[1..]).is_none());
+    }
+
+    #[test]
+    fn day_serde_round_trip() {
+        let mut day = AttendanceDay::new(NOON.into());
+        day.add_id(id(1), NOON, ScanMode::CheckInOut);
+
+        let json = serde_json::to_vec(&day).unwrap();
+        let loaded: AttendanceDay = serde_json::from_slice(&json).unwrap();
+
+        let attendance = loaded.attendances()[0];
+        assert_eq!(loaded.date, day.date);
+        assert_eq!(attendance.id, id(1));
+        assert_eq!((attendance.first, attendance.last), (NOON, NOON));
+        assert!(attendance.checked_in);
+    }
+
+    #[test]
+    fn legacy_day_is_loaded() {
+        let json = br#"{"date":20365,"ids":["00000000ABAB"]}"#;
+        let day: AttendanceDay = serde_json::from_slice(json).unwrap();
+
+        assert_eq!(day.ids().collect::<Vec<_>>(), [&id(0xAB)]);
+        assert_eq!(day.attendances()[0].last, 0);
+    }
+}
@@ -0,0 +1,16 @@
+//! Attendance storage logic, independent of the hardware
+//! so it can be tested on the host.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub use id_mapping::{IDMapping, Name};
+pub use id_store::{Attendance, AttendanceDay, IDStore, ScanMode, ScanResult, UnknownScan};
+
+pub mod day;
+mod id_mapping;
+mod id_store;
+pub mod memory;
+pub mod persistence;
+pub mod tally_id;
+pub mod timezone;
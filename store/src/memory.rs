@@ -0,0 +1,127 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+
+use crate::{
+    AttendanceDay, IDMapping, ScanMode,
+    day::Day,
+    persistence::{JournalEntry, Persistence, StorageError},
+};
+
+/// Keeps everything in RAM, for tests and the simulator
+#[derive(Clone, Default)]
+pub struct MemoryPersistence {
+    pub days: BTreeMap<Day, AttendanceDay>,
+    pub mapping: Option<IDMapping>,
+    pub mode: Option<ScanMode>,
+    pub journal: Vec<JournalEntry>,
+    /// Days that fail to load as if their data was corrupt
+    pub corrupt_days: BTreeSet<Day>,
+    /// The mapping fails to load as if its data was corrupt
+    pub corrupt_mapping: bool,
+    /// Simulates a removed storage medium, every access fails while this is set
+    pub unavailable: bool,
+    /// Simulates a switch to another medium, reported by the next reconnect
+    pub medium_changed: bool,
+}
+
+impl MemoryPersistence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check_available(&self) -> Result<(), StorageError> {
+        if self.unavailable {
+            Err(StorageError::Device)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Persistence for MemoryPersistence {
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError> {
+        self.check_available()?;
+        if self.corrupt_days.contains(&day) {
+            return Err(StorageError::Serialization);
+        }
+        Ok(self.days.get(&day).cloned())
+    }
+
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError> {
+        self.check_available()?;
+        self.days.insert(day, data.clone());
+        Ok(())
+    }
+
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError> {
+        self.check_available()?;
+        Ok(self.days.keys().copied().collect())
+    }
+
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError> {
+        self.check_available()?;
+        self.corrupt_days.remove(&day);
+        self.days.remove(&day);
+        Ok(())
+    }
+
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError> {
+        self.check_available()?;
+        if self.corrupt_mapping {
+            return Err(StorageError::Serialization);
+        }
+        Ok(self.mapping.clone())
+    }
+
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError> {
+        self.check_available()?;
+        self.mapping = Some(data.clone());
+        Ok(())
+    }
+
+    async fn discard_mapping(&mut self) -> Result<(), StorageError> {
+        self.check_available()?;
+        self.corrupt_mapping = false;
+        self.mapping = None;
+        Ok(())
+    }
+
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError> {
+        self.check_available()?;
+        Ok(self.mode)
+    }
+
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError> {
+        self.check_available()?;
+        self.mode = Some(mode);
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<bool, StorageError> {
+        Ok(core::mem::take(&mut self.medium_changed))
+    }
+
+    async fn journal_append(&mut self, entry: JournalEntry) -> Result<(), StorageError> {
+        self.journal.push(entry);
+        Ok(())
+    }
+
+    async fn journal_entries(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+        Ok(self.journal.clone())
+    }
+
+    async fn journal_clear(&mut self) -> Result<(), StorageError> {
+        self.journal.clear();
+        Ok(())
+    }
+
+    async fn journal_retain(
+        &mut self,
+        keep: impl Fn(&JournalEntry) -> bool,
+    ) -> Result<(), StorageError> {
+        self.journal.retain(keep);
+        Ok(())
+    }
+}
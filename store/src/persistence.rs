@@ -0,0 +1,79 @@
+use alloc::vec::Vec;
+
+use crate::{IDMapping, ScanMode, day::Day, id_store::AttendanceDay, tally_id::TallyID};
+
+/// Errors of a persistence layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    /// The storage device is missing or does not respond
+    Device,
+    /// The filesystem is missing, unsupported or corrupt
+    Filesystem,
+    /// There is no space left on the device
+    Full,
+    /// Any other error while reading or writing
+    Io,
+    /// Stored data could not be serialized or deserialized
+    Serialization,
+}
+
+/// A scan recorded in the write-ahead journal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub id: TallyID,
+    pub timestamp: u64,
+    pub mode: ScanMode,
+}
+
+// The firmware runs on a single threaded executor, the futures don't need to be Send
+#[allow(async_fn_in_trait)]
+pub trait Persistence {
+    /// Returns Ok(None) if no data is stored for the day
+    async fn load_day(&mut self, day: Day) -> Result<Option<AttendanceDay>, StorageError>;
+    async fn save_day(&mut self, day: Day, data: &AttendanceDay) -> Result<(), StorageError>;
+    async fn list_days(&mut self) -> Result<Vec<Day>, StorageError>;
+    /// Move an unreadable day out of the way so it is not loaded again.
+    /// Layers that can keep the data do so for a manual recovery.
+    async fn discard_day(&mut self, day: Day) -> Result<(), StorageError>;
+
+    /// Returns Ok(None) if no mapping is stored
+    async fn load_mapping(&mut self) -> Result<Option<IDMapping>, StorageError>;
+    async fn save_mapping(&mut self, data: &IDMapping) -> Result<(), StorageError>;
+    /// Move an unreadable mapping out of the way, like [`Self::discard_day`]
+    async fn discard_mapping(&mut self) -> Result<(), StorageError>;
+
+    /// Returns Ok(None) if no mode is stored
+    async fn load_mode(&mut self) -> Result<Option<ScanMode>, StorageError>;
+    async fn save_mode(&mut self, mode: ScanMode) -> Result<(), StorageError>;
+
+    /// Called when the storage medium was replaced, e.g. an SD card was inserted again.
+    /// Returns true if another medium is used from now on, its data has to be loaded again.
+    async fn reconnect(&mut self) -> Result<bool, StorageError> {
+        Ok(false)
+    }
+
+    /// Record a scan before it is written to the days.
+    /// Layers without a journal ignore this.
+    async fn journal_append(&mut self, _entry: JournalEntry) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// Scans that were recorded but not committed yet
+    async fn journal_entries(&mut self) -> Result<Vec<JournalEntry>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    /// Mark all recorded scans as committed
+    async fn journal_clear(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// Keep only the recorded scans `keep` returns true for,
+    /// the others are marked as committed
+    async fn journal_retain(
+        &mut self,
+        _keep: impl Fn(&JournalEntry) -> bool,
+    ) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use core::{fmt::Display, str::FromStr};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+/// Card number of an EM4100 tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TallyID([u8; 5]);
+
+impl TallyID {
+    /// Length of the card number in bytes
+    pub const LEN: usize = 5;
+
+    pub fn from_bytes(bytes: [u8; Self::LEN]) -> Self {
+        TallyID(bytes)
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        self.0
+    }
+}
+
+impl FromStr for TallyID {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.as_bytes().try_into()
+    }
+}
+
+impl TryFrom<heapless::String<12>> for TallyID {
+    type Error = ();
+
+    fn try_from(value: heapless::String<12>) -> Result<Self, Self::Error> {
+        value.as_bytes().try_into()
+    }
+}
+
+fn hex_val(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode `2 * N` hex chars of either case into `N` bytes
+pub fn decode_hex<const N: usize>(hex: &[u8]) -> Option<[u8; N]> {
+    if hex.len() != 2 * N {
+        return None;
+    }
+
+    let mut out = [0u8; N];
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = (hex_val(pair[0])? << 4) | hex_val(pair[1])?;
+    }
+
+    Some(out)
+}
+
+impl From<TallyID> for heapless::String<10> {
+    fn from(value: TallyID) -> Self {
+        const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
+        let mut s: Self = Self::new();
+
+        for &b in &value.0 {
+            // Should be safe to unwrap since the string is already long enough
+            s.push(HEX_CHARS[(b >> 4) as usize] as char).unwrap();
+            s.push(HEX_CHARS[(b & 0x0F) as usize] as char).unwrap();
+        }
+        s
+    }
+}
+
+/// From a array of hex chars of the card number.
+/// IDs saved by older firmware still contain the checksum of the reader frame,
+/// it was never verified and is dropped.
+impl TryFrom<&[u8]> for TallyID {
+    type Error = ();
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let number = match value.len() {
+            10 => value,
+            12 => &value[..10],
+            _ => return Err(()),
+        };
+
+        decode_hex(number).map(TallyID).ok_or(())
+    }
+}
+
+impl Display for TallyID {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let s: heapless::String<10> = (*self).into();
+        write!(f, "{}", s)
+    }
+}
+
+impl Serialize for TallyID {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s: heapless::String<10> = (*self).into();
+        serializer.serialize_str(&s)
+    }
+}
+
+impl<'de> Deserialize<'de> for TallyID {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(TallyIDVisitor)
+    }
+}
+
+/// Visitor that also accepts non borrowed strings,
+/// e.g. from buffered or untagged data
+struct TallyIDVisitor;
+
+impl de::Visitor<'_> for TallyIDVisitor {
+    type Value = TallyID;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a hex encoded tally ID")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        TallyID::from_str(v).map_err(|_| E::custom("Failed to parse Tally ID"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let id: TallyID = "0123456789".parse().unwrap();
+        assert_eq!(id.to_bytes(), [0x01, 0x23, 0x45, 0x67, 0x89]);
+
+        let s: heapless::String<10> = id.into();
+        assert_eq!(s, "0123456789");
+    }
+
+    #[test]
+    fn legacy_ids_drop_the_checksum() {
+        let id: TallyID = "0123456789AB".parse().unwrap();
+        assert_eq!(id.to_bytes(), [0x01, 0x23, 0x45, 0x67, 0x89]);
+    }
+
+    #[test]
+    fn invalid_ids_are_rejected() {
+        assert!("012345678".parse::<TallyID>().is_err());
+        assert!("0123456789A".parse::<TallyID>().is_err());
+        assert!("0123456789ABC".parse::<TallyID>().is_err());
+        assert!("012345678G".parse::<TallyID>().is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let id = TallyID::from_bytes([0x01, 0x23, 0x45, 0x67, 0x89]);
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, "\"0123456789\"");
+        assert_eq!(serde_json::from_str::<TallyID>(&json).unwrap(), id);
+    }
+}
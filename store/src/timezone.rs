@@ -0,0 +1,134 @@
+/// Timezone used for day boundaries and displayed dates
+pub const LOCAL_TIMEZONE: TimeZone = TimeZone::CentralEurope;
+
+const SECS_PER_HOUR: i64 = 3_600;
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Rule to convert UTC timestamps into local time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeZone {
+    /// Fixed offset to UTC in seconds
+    Fixed(i32),
+    /// CET (UTC+1) with CEST (UTC+2) from the last Sunday in March until the
+    /// last Sunday in October. Both switches happen at 01:00 UTC.
+    CentralEurope,
+}
+
+impl TimeZone {
+    /// Offset to UTC in seconds at the given UTC timestamp
+    pub fn offset(self, utc: u64) -> i64 {
+        match self {
+            TimeZone::Fixed(offset) => offset as i64,
+            TimeZone::CentralEurope => {
+                if is_eu_summer_time(utc as i64) {
+                    2 * SECS_PER_HOUR
+                } else {
+                    SECS_PER_HOUR
+                }
+            }
+        }
+    }
+
+    /// Convert a UTC timestamp into seconds since 1970-01-01 00:00 local time
+    pub fn to_local(self, utc: u64) -> u64 {
+        (utc as i64 + self.offset(utc)).max(0) as u64
+    }
+}
+
+/// EU summer time starts and ends at 01:00 UTC on the last Sunday of March
+/// and October respectively
+fn is_eu_summer_time(utc: i64) -> bool {
+    let (year, _, _) = civil_from_days(utc.div_euclid(SECS_PER_DAY));
+
+    let start = last_sunday(year, 3) * SECS_PER_DAY + SECS_PER_HOUR;
+    let end = last_sunday(year, 10) * SECS_PER_DAY + SECS_PER_HOUR;
+
+    (start..end).contains(&utc)
+}
+
+/// Days since epoch of the last Sunday of a month with 31 days
+fn last_sunday(year: i32, month: u32) -> i64 {
+    let last_day = days_from_civil(year, month, 31);
+    // 1970-01-01 was a Thursday, so day 0 has weekday 4 when counting from Sunday
+    let weekday = (last_day + 4).rem_euclid(7);
+    last_day - weekday
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+// Based on the algorithm by Howard Hinnant.
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = year as i64 - (month <= 2) as i64;
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400; // [0, 399]
+    let mp = (month as i64 + 9) % 12; // March = 0
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1; // [0, 365]
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy; // [0, 146096]
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian date (year, month, day) of days since 1970-01-01.
+// Based on the algorithm by Howard Hinnant.
+pub fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719_468; // shift epoch to 0000-03-01
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097; // [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365; // [0, 399]
+    let y = yoe + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // [0, 365]
+    let mp = (5 * doy + 2) / 153; // [0, 11]
+    let d = doy - (153 * mp + 2) / 5 + 1; // [1, 31]
+    let m = if mp < 10 { mp + 3 } else { mp - 9 }; // [1, 12]
+    ((y + (m <= 2) as i64) as i32, m as u32, d as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day::Day;
+
+    /// Switches of 2025 at 01:00 UTC
+    const SUMMER_START: u64 = 1_743_296_400; // 2025-03-30
+    const SUMMER_END: u64 = 1_761_440_400; // 2025-10-26
+    const HOUR: u64 = 3_600;
+
+    #[test]
+    fn offset_changes_at_the_switches() {
+        let tz = TimeZone::CentralEurope;
+
+        assert_eq!(tz.offset(SUMMER_START - 1), SECS_PER_HOUR);
+        assert_eq!(tz.offset(SUMMER_START), 2 * SECS_PER_HOUR);
+        assert_eq!(tz.offset(SUMMER_END - 1), 2 * SECS_PER_HOUR);
+        assert_eq!(tz.offset(SUMMER_END), SECS_PER_HOUR);
+    }
+
+    #[test]
+    fn last_sundays() {
+        assert_eq!(last_sunday(2025, 3), days_from_civil(2025, 3, 30));
+        assert_eq!(last_sunday(2025, 10), days_from_civil(2025, 10, 26));
+        // The last day of the month is a Sunday
+        assert_eq!(last_sunday(2024, 3), days_from_civil(2024, 3, 31));
+    }
+
+    #[test]
+    fn late_scans_on_switch_days_stay_on_their_day() {
+        // 23:30 local is 21:30 UTC in summer time
+        let march_30 = SUMMER_START - HOUR + 21 * HOUR + HOUR / 2;
+        assert_eq!(Day::from(march_30).to_ymd(), (2025, 3, 30));
+        assert_eq!(Day::from(march_30 + HOUR).to_ymd(), (2025, 3, 31));
+
+        // and 22:30 UTC in winter time
+        let october_26 = SUMMER_END - HOUR + 22 * HOUR + HOUR / 2;
+        assert_eq!(Day::from(october_26).to_ymd(), (2025, 10, 26));
+        assert_eq!(Day::from(october_26 + HOUR).to_ymd(), (2025, 10, 27));
+    }
+
+    #[test]
+    fn civil_round_trip() {
+        for days in [-1, 0, 11_016, 20_177, 47_482] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+}
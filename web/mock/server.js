@@ -4,11 +4,11 @@ import bodyParser from "body-parser";
 const app = express();
 const port = 3000;
 
-app.use(bodyParser.json());
+app.use(bodyParser.json({ strict: false }));
 
 let mappings = [
   [
-    "123456789ABC",
+    "123456789A",
     {
       first: "Feuerwehrman",
       last: "Sam",
@@ -19,7 +19,7 @@ let mappings = [
 function generateRandomId() {
   const chars = "ABCDEF0123456789";
   let id = "";
-  for (let i = 0; i < 12; i++) {
+  for (let i = 0; i < 10; i++) {
     id += chars.charAt(Math.floor(Math.random() * chars.length));
   }
   return id;
@@ -46,11 +46,69 @@ app.post("/api/mapping", (req, res) => {
 
   // Add new mapping
   mappings.push([id, name]);
+  unknown = unknown.filter((scan) => scan.id !== id);
 
   res.status(201).send("");
 });
 
 
+let unknown = [];
+
+// GET /api/unknown
+app.get("/api/unknown", (req, res) => {
+  res.json(unknown);
+});
+
+let mode = "attendance";
+
+// GET /api/mode
+app.get("/api/mode", (req, res) => {
+  res.json(mode);
+});
+
+// POST /api/mode
+app.post("/api/mode", (req, res) => {
+  mode = req.body;
+  res.status(200).send("");
+});
+
+let timeOffset = 0;
+
+// GET /api/time
+app.get("/api/time", (req, res) => {
+  res.json({ timestamp: Math.floor(Date.now() / 1000) + timeOffset });
+});
+
+// POST /api/time
+app.post("/api/time", (req, res) => {
+  const { timestamp } = req.body;
+  if (!(timestamp >= 946684800 && timestamp < 4102444800)) {
+    res.status(400).send("Time outside of 2000 to 2099");
+    return;
+  }
+  timeOffset = req.body.timestamp - Math.floor(Date.now() / 1000);
+  res.status(200).send("");
+});
+
+let holdOffMillis = 2000;
+let signalRejected = false;
+
+// GET /api/holdoff
+app.get("/api/holdoff", (req, res) => {
+  res.json({ millis: holdOffMillis, signal_rejected: signalRejected });
+});
+
+// POST /api/holdoff
+app.post("/api/holdoff", (req, res) => {
+  if (req.body.millis > 60000) {
+    res.status(400).send("Hold-off window too long");
+    return;
+  }
+  holdOffMillis = req.body.millis;
+  signalRejected = req.body.signal_rejected;
+  res.status(200).send("");
+});
+
 // SSE route: /api/idevent
 app.get("/api/idevent", (req, res) => {
   // Set headers for SSE
@@ -63,7 +121,12 @@ app.get("/api/idevent", (req, res) => {
   // Send initial event
   const sendEvent = () => {
     const id = generateRandomId();
-    res.write(`data: ${id}\n\n`);
+    // Scans of IDs that were already counted today are sent as "duplicate"
+    const event = Math.random() < 0.3 ? "duplicate" : "msg";
+    // The random IDs have no name, like new cards
+    unknown = unknown.filter((scan) => scan.id !== id).slice(-15);
+    unknown.push({ id, last_seen: Math.floor(Date.now() / 1000) });
+    res.write(`event: ${event}\ndata: ${id}\n\n`);
   };
 
   // Send immediately and then every 10 seconds